@@ -4,7 +4,7 @@ extern crate icaparse;
 
 extern crate test;
 
-const REQ: &'static [u8] = b"\
+const REQ: &[u8] = b"\
 RESPMOD /wp-content/uploads/2010/03/hello-kitty-darth-vader-pink.jpg ICAP/1.0\r\n\
 Host: www.kittyhell.com\r\n\
 User-Agent: Mozilla/5.0 (Macintosh; U; Intel Mac OS X 10.6; ja-JP-mac; rv:1.9.2.3) Gecko/20100401 Firefox/3.6.3 Pathtraq/0.9\r\n\
@@ -27,7 +27,7 @@ fn bench_icaparse(b: &mut test::Bencher) {
     b.bytes = REQ.len() as u64;
 }
 
-const OPTIONS_REQ: &'static [u8] = b"\
+const OPTIONS_REQ: &[u8] = b"\
 OPTIONS icap://icap.server.net/sample-service ICAP/1.0\r\n\
 Host: icap.server.net\r\n\
 User-Agent: BazookaDotCom-ICAP-Client-Library/2.3\r\n\r\n";
@@ -42,7 +42,7 @@ fn bench_icaparse_options(b: &mut test::Bencher) {
     b.bytes = OPTIONS_REQ.len() as u64;
 }
 
-const REQMOD_GET_REQ: &'static [u8] = b"\
+const REQMOD_GET_REQ: &[u8] = b"\
 REQMOD icap://icap-server.net/server?arg=87 ICAP/1.0\r\n\
 Host: icap-server.net\r\n\
 Encapsulated: req-hdr=0, null-body=170\r\n\
@@ -64,7 +64,7 @@ fn bench_icaparse_reqmod_get(b: &mut test::Bencher) {
     b.bytes = REQMOD_GET_REQ.len() as u64;
 }
 
-const REQMOD_POST_REQ: &'static [u8] = b"\
+const REQMOD_POST_REQ: &[u8] = b"\
 REQMOD icap://icap-server.net/server?arg=87 ICAP/1.0\r\n\
 Host: icap-server.net\r\n\
 Encapsulated: req-hdr=0, req-body=147\r\n\
@@ -89,7 +89,7 @@ fn bench_icaparse_reqmod_post(b: &mut test::Bencher) {
     b.bytes = REQMOD_POST_REQ.len() as u64;
 }
 
-const RESPMOD_REQ: &'static [u8] = b"\
+const RESPMOD_REQ: &[u8] = b"\
 RESPMOD icap://icap.example.org/satisf ICAP/1.0\r\n\
 Host: icap.example.org\r\n\
 Encapsulated: req-hdr=0, res-hdr=137, res-body=296\r\n\
@@ -0,0 +1,587 @@
+#[cfg(feature = "std")]
+use std::io;
+
+use core::{fmt, result};
+
+use {ends_with_empty_line, is_header_name_token, is_header_value_token, is_token, Error, Header, IcapStatus,
+     SectionType};
+
+/// An error encoding a message.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EncodeError {
+    /// The buffer is too small to hold the message.
+    BufferTooSmall,
+    /// A part of the message would not parse back as written, such as a
+    /// header value with a line break. Nothing was written.
+    Invalid(Error),
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EncodeError::BufferTooSmall => f.write_str("buffer too small"),
+            EncodeError::Invalid(ref err) => write!(f, "invalid message: {}", err),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl ::std::error::Error for EncodeError {}
+
+impl From<Error> for EncodeError {
+    fn from(err: Error) -> EncodeError {
+        EncodeError::Invalid(err)
+    }
+}
+
+/// A destination for encoded bytes.
+trait Sink {
+    type Error;
+
+    fn put(&mut self, bytes: &[u8]) -> result::Result<(), Self::Error>;
+}
+
+/// Counts the encoded bytes without writing them.
+struct Counter(usize);
+
+impl Sink for Counter {
+    type Error = ();
+
+    #[inline]
+    fn put(&mut self, bytes: &[u8]) -> result::Result<(), ()> {
+        self.0 += bytes.len();
+        Ok(())
+    }
+}
+
+struct SliceSink<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+}
+
+impl<'a> Sink for SliceSink<'a> {
+    type Error = EncodeError;
+
+    #[inline]
+    fn put(&mut self, bytes: &[u8]) -> result::Result<(), EncodeError> {
+        let end = self.pos + bytes.len();
+        if end > self.buf.len() {
+            return Err(EncodeError::BufferTooSmall);
+        }
+        self.buf[self.pos..end].copy_from_slice(bytes);
+        self.pos = end;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+struct IoSink<'a, W: io::Write + 'a> {
+    dst: &'a mut W,
+    count: usize,
+}
+
+#[cfg(feature = "std")]
+impl<'a, W: io::Write> Sink for IoSink<'a, W> {
+    type Error = io::Error;
+
+    #[inline]
+    fn put(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.dst.write_all(bytes)?;
+        self.count += bytes.len();
+        Ok(())
+    }
+}
+
+/// Writes `n` in the given radix, without allocating.
+fn put_number<S: Sink>(out: &mut S, mut n: u64, radix: u64) -> result::Result<(), S::Error> {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+    let mut buf = [0u8; 20];
+    let mut i = buf.len();
+    loop {
+        i -= 1;
+        buf[i] = DIGITS[(n % radix) as usize];
+        n /= radix;
+        if n == 0 {
+            break;
+        }
+    }
+    out.put(&buf[i..])
+}
+
+#[inline]
+fn put_version<S: Sink>(out: &mut S, version: u8) -> result::Result<(), S::Error> {
+    out.put(b"ICAP/1.")?;
+    put_number(out, u64::from(version), 10)
+}
+
+/// Checks that `bytes` is a token without spaces, such as a method or URI.
+fn check_token(bytes: &[u8], err: Error) -> result::Result<(), Error> {
+    if !bytes.is_empty() && bytes.iter().all(|&b| is_token(b) && b != b' ') {
+        Ok(())
+    } else {
+        Err(err)
+    }
+}
+
+/// Checks that `value` has no line breaks, so it fits on one line.
+fn check_value(value: &[u8], err: Error) -> result::Result<(), Error> {
+    if value.iter().all(|&b| is_header_value_token(b)) {
+        Ok(())
+    } else {
+        Err(err)
+    }
+}
+
+/// Checks that `reason` has only the bytes a reason-phrase is parsed with:
+/// HTAB, SP and visible ASCII.
+fn check_reason(reason: &[u8]) -> result::Result<(), Error> {
+    if reason.iter().all(|&b| (0x20..=0x7E).contains(&b) || b == b'\t') {
+        Ok(())
+    } else {
+        Err(Error::Status)
+    }
+}
+
+fn check_headers(headers: &[Header]) -> result::Result<(), Error> {
+    for header in headers {
+        if header.name.is_empty() || !header.name.bytes().all(is_header_name_token) {
+            return Err(Error::HeaderName);
+        }
+        check_value(header.value, Error::HeaderValue)?;
+    }
+    Ok(())
+}
+
+/// Reports an invalid message to an `io::Write` caller.
+#[cfg(feature = "std")]
+fn invalid_input(err: Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, EncodeError::Invalid(err))
+}
+
+fn put_headers<S: Sink>(out: &mut S, headers: &[Header], skip_istag: bool)
+        -> result::Result<(), S::Error> {
+    for header in headers {
+        // the Encapsulated header is computed from the sections
+        if header.name.eq_ignore_ascii_case("Encapsulated") {
+            continue;
+        }
+        // an ISTag set on the builder replaces any in the headers
+        if skip_istag && header.name.eq_ignore_ascii_case("ISTag") {
+            continue;
+        }
+        out.put(header.name.as_bytes())?;
+        out.put(b": ")?;
+        out.put(header.value)?;
+        out.put(b"\r\n")?;
+    }
+    Ok(())
+}
+
+/// The encapsulated HTTP message parts of an ICAP message.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+struct Parts<'a> {
+    req_hdr: Option<&'a [u8]>,
+    res_hdr: Option<&'a [u8]>,
+    body: Option<(SectionType, &'a [u8])>,
+}
+
+impl<'a> Parts<'a> {
+    /// Checks that each encapsulated HTTP header ends with an empty line, so
+    /// the next section starts at its offset.
+    fn check(&self) -> result::Result<(), Error> {
+        for hdr in self.req_hdr.iter().chain(self.res_hdr.iter()) {
+            if !ends_with_empty_line(hdr) {
+                return Err(Error::SectionOffset);
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the `Encapsulated` header, with the offset of each section.
+    fn put_encapsulated<S: Sink>(&self, out: &mut S) -> result::Result<(), S::Error> {
+        out.put(b"Encapsulated: ")?;
+        let mut offset = 0;
+        let mut first = true;
+        let mut entry = |out: &mut S, name: &[u8], offset: usize| {
+            if !first {
+                out.put(b", ")?;
+            }
+            first = false;
+            out.put(name)?;
+            out.put(b"=")?;
+            put_number(out, offset as u64, 10)
+        };
+        if let Some(req_hdr) = self.req_hdr {
+            entry(out, b"req-hdr", offset)?;
+            offset += req_hdr.len();
+        }
+        if let Some(res_hdr) = self.res_hdr {
+            entry(out, b"res-hdr", offset)?;
+            offset += res_hdr.len();
+        }
+        match self.body {
+            Some((SectionType::RequestBody, _)) => entry(out, b"req-body", offset)?,
+            Some((SectionType::ResponseBody, _)) => entry(out, b"res-body", offset)?,
+            Some((SectionType::OptionsBody, _)) => entry(out, b"opt-body", offset)?,
+            _ => entry(out, b"null-body", offset)?,
+        }
+        out.put(b"\r\n")
+    }
+
+    /// Writes the encapsulated sections, chunk-encoding the body.
+    fn put_payload<S: Sink>(&self, out: &mut S) -> result::Result<(), S::Error> {
+        if let Some(req_hdr) = self.req_hdr {
+            out.put(req_hdr)?;
+        }
+        if let Some(res_hdr) = self.res_hdr {
+            out.put(res_hdr)?;
+        }
+        if let Some((_, body)) = self.body {
+            if !body.is_empty() {
+                put_number(out, body.len() as u64, 16)?;
+                out.put(b"\r\n")?;
+                out.put(body)?;
+                out.put(b"\r\n")?;
+            }
+            out.put(b"0\r\n\r\n")?;
+        }
+        Ok(())
+    }
+}
+
+/// A builder for ICAP requests.
+///
+/// The builder borrows every part of the request, and writes it out without
+/// allocating. The `Encapsulated` header is computed from the encapsulated
+/// HTTP parts that were set, and the body is chunk-encoded.
+///
+/// # Example
+///
+/// ```
+/// let headers = [icaparse::Header { name: "Host", value: b"icap-server.net" }];
+/// let req = icaparse::RequestBuilder::new("REQMOD", "icap://icap-server.net/server")
+///     .headers(&headers)
+///     .req_hdr(b"POST / HTTP/1.1\r\nHost: www.origin-server.com\r\n\r\n")
+///     .req_body(b"Rust");
+///
+/// let mut buf = [0; 256];
+/// let len = req.encode(&mut buf).unwrap();
+/// assert_eq!(&buf[..len], &b"REQMOD icap://icap-server.net/server ICAP/1.0\r\n\
+/// Host: icap-server.net\r\n\
+/// Encapsulated: req-hdr=0, req-body=48\r\n\
+/// \r\n\
+/// POST / HTTP/1.1\r\nHost: www.origin-server.com\r\n\r\n\
+/// 4\r\nRust\r\n0\r\n\r\n"[..]);
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RequestBuilder<'a> {
+    method: &'a str,
+    uri: &'a str,
+    version: u8,
+    headers: &'a [Header<'a>],
+    parts: Parts<'a>,
+}
+
+impl<'a> RequestBuilder<'a> {
+    /// Creates a builder for a request with the given method, such as
+    /// `RESPMOD`, and ICAP URI.
+    #[inline]
+    pub fn new(method: &'a str, uri: &'a str) -> RequestBuilder<'a> {
+        RequestBuilder {
+            method,
+            uri,
+            version: 0,
+            headers: &[],
+            parts: Parts::default(),
+        }
+    }
+
+    /// Sets the minor version of the request, `0` for `ICAP/1.0`.
+    #[inline]
+    pub fn version(mut self, version: u8) -> RequestBuilder<'a> {
+        self.version = version;
+        self
+    }
+
+    /// Sets the ICAP headers of the request.
+    ///
+    /// Any `Encapsulated` header is skipped, as it is computed from the
+    /// encapsulated parts.
+    #[inline]
+    pub fn headers(mut self, headers: &'a [Header<'a>]) -> RequestBuilder<'a> {
+        self.headers = headers;
+        self
+    }
+
+    /// Sets the encapsulated HTTP request header, including the empty line
+    /// that ends it.
+    #[inline]
+    pub fn req_hdr(mut self, req_hdr: &'a [u8]) -> RequestBuilder<'a> {
+        self.parts.req_hdr = Some(req_hdr);
+        self
+    }
+
+    /// Sets the encapsulated HTTP response header, including the empty line
+    /// that ends it.
+    #[inline]
+    pub fn res_hdr(mut self, res_hdr: &'a [u8]) -> RequestBuilder<'a> {
+        self.parts.res_hdr = Some(res_hdr);
+        self
+    }
+
+    /// Sets the encapsulated HTTP request body, which will be chunk-encoded.
+    #[inline]
+    pub fn req_body(mut self, body: &'a [u8]) -> RequestBuilder<'a> {
+        self.parts.body = Some((SectionType::RequestBody, body));
+        self
+    }
+
+    /// Sets the encapsulated HTTP response body, which will be chunk-encoded.
+    #[inline]
+    pub fn res_body(mut self, body: &'a [u8]) -> RequestBuilder<'a> {
+        self.parts.body = Some((SectionType::ResponseBody, body));
+        self
+    }
+
+    /// Checks that every part of the request can be written as given.
+    fn check(&self) -> result::Result<(), Error> {
+        check_token(self.method.as_bytes(), Error::Token)?;
+        check_token(self.uri.as_bytes(), Error::Uri)?;
+        check_headers(self.headers)?;
+        self.parts.check()
+    }
+
+    fn put<S: Sink>(&self, out: &mut S) -> result::Result<(), S::Error> {
+        out.put(self.method.as_bytes())?;
+        out.put(b" ")?;
+        out.put(self.uri.as_bytes())?;
+        out.put(b" ")?;
+        put_version(out, self.version)?;
+        out.put(b"\r\n")?;
+        put_headers(out, self.headers, false)?;
+        self.parts.put_encapsulated(out)?;
+        out.put(b"\r\n")?;
+        self.parts.put_payload(out)
+    }
+
+    /// The number of bytes the encoded request takes.
+    pub fn encoded_len(&self) -> usize {
+        let mut counter = Counter(0);
+        let _ = self.put(&mut counter);
+        counter.0
+    }
+
+    /// Writes the request into `buf`, returning the number of bytes written.
+    ///
+    /// The method and URI must be tokens without spaces, the headers must
+    /// have valid names and values without line breaks, and each
+    /// encapsulated HTTP header must end with an empty line.
+    pub fn encode(&self, buf: &mut [u8]) -> result::Result<usize, EncodeError> {
+        self.check()?;
+        let mut out = SliceSink { buf, pos: 0 };
+        self.put(&mut out)?;
+        Ok(out.pos)
+    }
+
+    /// Writes the request to `dst`, returning the number of bytes written.
+    ///
+    /// An invalid request is an `InvalidInput` error, and nothing is written.
+    #[cfg(feature = "std")]
+    pub fn write_to<W: io::Write>(&self, dst: &mut W) -> io::Result<usize> {
+        self.check().map_err(invalid_input)?;
+        let mut out = IoSink { dst, count: 0 };
+        self.put(&mut out)?;
+        Ok(out.count)
+    }
+}
+
+/// A builder for ICAP responses.
+///
+/// Like `RequestBuilder`, the builder borrows every part of the response and
+/// writes it out without allocating. The `Encapsulated` header is computed
+/// from the encapsulated HTTP parts that were set, and is written as
+/// `null-body=0` when there are none. Informational (`1xx`) responses, such
+/// as `100 Continue`, are written without an `Encapsulated` header.
+///
+/// # Example
+///
+/// ```
+/// let res = icaparse::ResponseBuilder::new(200, "OK")
+///     .istag(b"\"W3E4R7U9-L2E4-2\"")
+///     .res_hdr(b"HTTP/1.1 200 OK\r\n\r\n")
+///     .res_body(b"Rust");
+///
+/// let mut buf = [0; 128];
+/// let len = res.encode(&mut buf).unwrap();
+/// assert_eq!(&buf[..len], &b"ICAP/1.0 200 OK\r\n\
+/// ISTag: \"W3E4R7U9-L2E4-2\"\r\n\
+/// Encapsulated: res-hdr=0, res-body=19\r\n\
+/// \r\n\
+/// HTTP/1.1 200 OK\r\n\r\n\
+/// 4\r\nRust\r\n0\r\n\r\n"[..]);
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ResponseBuilder<'a> {
+    version: u8,
+    code: u16,
+    reason: &'a str,
+    istag: Option<&'a [u8]>,
+    headers: &'a [Header<'a>],
+    parts: Parts<'a>,
+}
+
+impl<'a> ResponseBuilder<'a> {
+    /// Creates a builder for a response with the given code, such as `200`,
+    /// and reason-phrase, such as `OK`.
+    #[inline]
+    pub fn new(code: u16, reason: &'a str) -> ResponseBuilder<'a> {
+        ResponseBuilder {
+            version: 0,
+            code,
+            reason,
+            istag: None,
+            headers: &[],
+            parts: Parts::default(),
+        }
+    }
+
+    /// Creates a builder for a response with the given status, and its
+    /// canonical reason-phrase.
+    ///
+    /// A status without a canonical reason-phrase gets an empty one.
+    #[inline]
+    pub fn from_status(status: IcapStatus) -> ResponseBuilder<'a> {
+        ResponseBuilder::new(status.as_u16(), status.canonical_reason().unwrap_or(""))
+    }
+
+    /// Sets the minor version of the response, `0` for `ICAP/1.0`.
+    #[inline]
+    pub fn version(mut self, version: u8) -> ResponseBuilder<'a> {
+        self.version = version;
+        self
+    }
+
+    /// Sets the value of the `ISTag` header, including its quotes.
+    ///
+    /// Any `ISTag` header in `headers` is then skipped.
+    #[inline]
+    pub fn istag(mut self, istag: &'a [u8]) -> ResponseBuilder<'a> {
+        self.istag = Some(istag);
+        self
+    }
+
+    /// Sets the ICAP headers of the response.
+    ///
+    /// Any `Encapsulated` header is skipped, as it is computed from the
+    /// encapsulated parts.
+    #[inline]
+    pub fn headers(mut self, headers: &'a [Header<'a>]) -> ResponseBuilder<'a> {
+        self.headers = headers;
+        self
+    }
+
+    /// Sets the encapsulated HTTP request header, including the empty line
+    /// that ends it.
+    #[inline]
+    pub fn req_hdr(mut self, req_hdr: &'a [u8]) -> ResponseBuilder<'a> {
+        self.parts.req_hdr = Some(req_hdr);
+        self
+    }
+
+    /// Sets the encapsulated HTTP response header, including the empty line
+    /// that ends it.
+    #[inline]
+    pub fn res_hdr(mut self, res_hdr: &'a [u8]) -> ResponseBuilder<'a> {
+        self.parts.res_hdr = Some(res_hdr);
+        self
+    }
+
+    /// Sets the encapsulated HTTP request body, which will be chunk-encoded.
+    #[inline]
+    pub fn req_body(mut self, body: &'a [u8]) -> ResponseBuilder<'a> {
+        self.parts.body = Some((SectionType::RequestBody, body));
+        self
+    }
+
+    /// Sets the encapsulated HTTP response body, which will be chunk-encoded.
+    #[inline]
+    pub fn res_body(mut self, body: &'a [u8]) -> ResponseBuilder<'a> {
+        self.parts.body = Some((SectionType::ResponseBody, body));
+        self
+    }
+
+    /// Sets the body of an OPTIONS response, which will be chunk-encoded.
+    #[inline]
+    pub fn opt_body(mut self, body: &'a [u8]) -> ResponseBuilder<'a> {
+        self.parts.body = Some((SectionType::OptionsBody, body));
+        self
+    }
+
+    /// Checks that every part of the response can be written as given.
+    fn check(&self) -> result::Result<(), Error> {
+        if self.code < 100 || self.code > 999 {
+            return Err(Error::Status);
+        }
+        check_reason(self.reason.as_bytes())?;
+        if let Some(istag) = self.istag {
+            check_value(istag, Error::HeaderValue)?;
+        }
+        check_headers(self.headers)?;
+        self.parts.check()
+    }
+
+    fn put<S: Sink>(&self, out: &mut S) -> result::Result<(), S::Error> {
+        put_version(out, self.version)?;
+        out.put(b" ")?;
+        put_number(out, u64::from(self.code), 10)?;
+        out.put(b" ")?;
+        out.put(self.reason.as_bytes())?;
+        out.put(b"\r\n")?;
+        if let Some(istag) = self.istag {
+            out.put(b"ISTag: ")?;
+            out.put(istag)?;
+            out.put(b"\r\n")?;
+        }
+        put_headers(out, self.headers, self.istag.is_some())?;
+        let informational = self.code >= 100 && self.code < 200;
+        if !informational {
+            self.parts.put_encapsulated(out)?;
+        }
+        out.put(b"\r\n")?;
+        if !informational {
+            self.parts.put_payload(out)?;
+        }
+        Ok(())
+    }
+
+    /// The number of bytes the encoded response takes.
+    pub fn encoded_len(&self) -> usize {
+        let mut counter = Counter(0);
+        let _ = self.put(&mut counter);
+        counter.0
+    }
+
+    /// Writes the response into `buf`, returning the number of bytes written.
+    ///
+    /// The code must have three digits, the reason-phrase must have only
+    /// tabs, spaces and visible ASCII, the `ISTag` must not have line breaks,
+    /// and the headers and encapsulated HTTP headers must be valid as for
+    /// `RequestBuilder::encode`.
+    pub fn encode(&self, buf: &mut [u8]) -> result::Result<usize, EncodeError> {
+        self.check()?;
+        let mut out = SliceSink { buf, pos: 0 };
+        self.put(&mut out)?;
+        Ok(out.pos)
+    }
+
+    /// Writes the response to `dst`, returning the number of bytes written.
+    ///
+    /// An invalid response is an `InvalidInput` error, and nothing is written.
+    #[cfg(feature = "std")]
+    pub fn write_to<W: io::Write>(&self, dst: &mut W) -> io::Result<usize> {
+        self.check().map_err(invalid_input)?;
+        let mut out = IoSink { dst, count: 0 };
+        self.put(&mut out)?;
+        Ok(out.count)
+    }
+}
@@ -0,0 +1,450 @@
+use core::cmp;
+
+use iter::Bytes;
+use {parse_chunk_head, Error, InvalidChunkSize, ParserConfig, Result, Status};
+
+/// A piece of a chunked body, as returned by `ChunkedDecoder::decode`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Chunk<'a> {
+    /// The size line of a data chunk.
+    Head {
+        /// The number of data bytes in the chunk.
+        size: u64,
+        /// The chunk extensions.
+        extensions: ChunkExtensions<'a>,
+    },
+    /// Bytes of chunk data.
+    ///
+    /// A chunk may be returned in several pieces when it is not completely
+    /// contained in the buffer.
+    Data(&'a [u8]),
+    /// The terminating zero-sized chunk, along with its extensions.
+    ///
+    /// Any trailer following the last chunk has been consumed. When decoding a
+    /// preview, this is only returned if the chunk carries the `ieof`
+    /// extension, meaning the preview contained the whole body.
+    Last {
+        /// The chunk extensions.
+        extensions: ChunkExtensions<'a>,
+    },
+    /// The zero-sized chunk ending a preview that did not contain the whole
+    /// body.
+    ///
+    /// The rest of the body follows once the server has replied
+    /// `ICAP/1.0 100 Continue`; call `ChunkedDecoder::resume` to decode it.
+    PreviewEnd {
+        /// The chunk extensions.
+        extensions: ChunkExtensions<'a>,
+    },
+}
+
+/// The extensions of a chunk size line.
+///
+/// > ```notrust
+/// > chunk-ext      = *( ";" chunk-ext-name [ "=" chunk-ext-val ] )
+/// > ```
+///
+/// ICAP uses the `ieof` extension on the last chunk of a preview to signal
+/// that the preview contained the whole body (RFC 3507, section 4.5).
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ChunkExtensions<'a> {
+    raw: &'a [u8],
+}
+
+impl<'a> ChunkExtensions<'a> {
+    #[inline]
+    pub(crate) fn new(raw: &'a [u8]) -> ChunkExtensions<'a> {
+        ChunkExtensions { raw }
+    }
+
+    /// The raw bytes following the first `;` of the size line.
+    #[inline]
+    pub fn raw(&self) -> &'a [u8] {
+        self.raw
+    }
+
+    /// Returns true if the size line had no extensions.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+
+    /// Iterates over the extensions, in order.
+    #[inline]
+    pub fn iter(&self) -> ChunkExtensionsIter<'a> {
+        ChunkExtensionsIter { rest: self.raw }
+    }
+
+    /// Finds the extension with the given name, ignoring case.
+    pub fn get(&self, name: &str) -> Option<ChunkExtension<'a>> {
+        self.iter().find(|ext| ext.name.eq_ignore_ascii_case(name.as_bytes()))
+    }
+
+    /// Returns true if the `ieof` extension is present.
+    #[inline]
+    pub fn ieof(&self) -> bool {
+        self.get("ieof").is_some()
+    }
+}
+
+impl<'a> IntoIterator for ChunkExtensions<'a> {
+    type Item = ChunkExtension<'a>;
+    type IntoIter = ChunkExtensionsIter<'a>;
+
+    fn into_iter(self) -> ChunkExtensionsIter<'a> {
+        self.iter()
+    }
+}
+
+/// A single chunk extension.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ChunkExtension<'a> {
+    /// The name of the extension, such as `ieof`.
+    pub name: &'a [u8],
+    /// The value of the extension, if any, with the quotes of a quoted-string
+    /// removed.
+    pub value: Option<&'a [u8]>,
+}
+
+/// An iterator over the extensions of a chunk size line.
+#[derive(Clone, Debug)]
+pub struct ChunkExtensionsIter<'a> {
+    rest: &'a [u8],
+}
+
+impl<'a> Iterator for ChunkExtensionsIter<'a> {
+    type Item = ChunkExtension<'a>;
+
+    fn next(&mut self) -> Option<ChunkExtension<'a>> {
+        loop {
+            if self.rest.is_empty() {
+                return None;
+            }
+            // find the `;` ending this extension, skipping over quoted-strings
+            let mut quoted = false;
+            let mut escaped = false;
+            let mut end = self.rest.len();
+            for (i, &b) in self.rest.iter().enumerate() {
+                if escaped {
+                    escaped = false;
+                } else if quoted {
+                    match b {
+                        b'\\' => escaped = true,
+                        b'"' => quoted = false,
+                        _ => {}
+                    }
+                } else if b == b'"' {
+                    quoted = true;
+                } else if b == b';' {
+                    end = i;
+                    break;
+                }
+            }
+            let ext = &self.rest[..end];
+            self.rest = if end < self.rest.len() { &self.rest[end + 1..] } else { &[] };
+
+            let (name, value) = match ext.iter().position(|&b| b == b'=') {
+                Some(i) => (trim(&ext[..i]), Some(unquote(trim(&ext[i + 1..])))),
+                None => (trim(ext), None),
+            };
+            if !name.is_empty() {
+                return Some(ChunkExtension { name, value });
+            }
+        }
+    }
+}
+
+#[inline]
+fn trim(mut bytes: &[u8]) -> &[u8] {
+    while let Some((&b, rest)) = bytes.split_first() {
+        if b != b' ' && b != b'\t' {
+            break;
+        }
+        bytes = rest;
+    }
+    while let Some((&b, rest)) = bytes.split_last() {
+        if b != b' ' && b != b'\t' {
+            break;
+        }
+        bytes = rest;
+    }
+    bytes
+}
+
+#[inline]
+fn unquote(bytes: &[u8]) -> &[u8] {
+    if bytes.len() >= 2 && bytes[0] == b'"' && bytes[bytes.len() - 1] == b'"' {
+        &bytes[1..bytes.len() - 1]
+    } else {
+        bytes
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum State {
+    Size,
+    Data(u64),
+    DataEnd,
+    PreviewEnd,
+    Done,
+}
+
+/// A streaming decoder for chunked bodies, such as the `req-body` and
+/// `res-body` sections of an ICAP message.
+///
+/// The decoder does not buffer anything itself. Each call to `decode` reads
+/// from the start of the given buffer and returns how many bytes were
+/// consumed, along with the `Chunk` found there. The caller should drop the
+/// consumed bytes and call `decode` again with what remains. If the buffer does
+/// not hold enough bytes to make progress, `Status::Partial` is returned and
+/// nothing is consumed.
+///
+/// # Example
+///
+/// ```
+/// use icaparse::{Chunk, ChunkedDecoder, Status};
+///
+/// let mut buf = &b"4\r\nRust\r\n0\r\n\r\n"[..];
+/// let mut decoder = ChunkedDecoder::new();
+/// let mut body = Vec::new();
+/// while !decoder.is_done() {
+///     match decoder.decode(buf).unwrap() {
+///         Status::Complete((n, chunk)) => {
+///             if let Chunk::Data(data) = chunk {
+///                 body.extend_from_slice(data);
+///             }
+///             buf = &buf[n..];
+///         },
+///         Status::Partial => unreachable!("read more bytes into the buffer"),
+///     }
+/// }
+/// assert_eq!(body, b"Rust");
+/// ```
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ChunkedDecoder {
+    state: State,
+    preview: bool,
+    max_size: Option<u64>,
+    max_line_len: Option<usize>,
+}
+
+impl ChunkedDecoder {
+    /// Creates a decoder positioned at the start of a chunked body.
+    #[inline]
+    pub fn new() -> ChunkedDecoder {
+        ChunkedDecoder {
+            state: State::Size,
+            preview: false,
+            max_size: None,
+            max_line_len: None,
+        }
+    }
+
+    /// Creates a decoder positioned at the start of a preview body, as sent
+    /// with a request carrying a `Preview` header.
+    ///
+    /// The zero-sized chunk ending the preview is returned as `Chunk::Last` if
+    /// it carries the `ieof` extension, and as `Chunk::PreviewEnd` otherwise.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use icaparse::{Chunk, ChunkedDecoder, Status};
+    ///
+    /// let mut decoder = ChunkedDecoder::preview();
+    /// match decoder.decode(b"0\r\n\r\n").unwrap() {
+    ///     Status::Complete((5, Chunk::PreviewEnd { .. })) => {},
+    ///     _ => unreachable!(),
+    /// }
+    /// assert!(decoder.is_preview_end());
+    ///
+    /// // after writing `ICAP/1.0 100 Continue`
+    /// decoder.resume();
+    /// match decoder.decode(b"4\r\nRust\r\n0\r\n\r\n").unwrap() {
+    ///     Status::Complete((3, Chunk::Head { size: 4, .. })) => {},
+    ///     _ => unreachable!(),
+    /// }
+    /// ```
+    #[inline]
+    pub fn preview() -> ChunkedDecoder {
+        ChunkedDecoder {
+            state: State::Size,
+            preview: true,
+            max_size: None,
+            max_line_len: None,
+        }
+    }
+
+    /// Applies the limits of a `ParserConfig` to the decoded body.
+    ///
+    /// A chunk larger than `ParserConfig::max_chunk_size` is rejected with
+    /// `Error::ChunkTooLarge`, and a size or trailer line longer than
+    /// `ParserConfig::max_chunk_line_len` with `Error::LineTooLong`. A line is
+    /// rejected as soon as it is too long, so a body that never ends its line
+    /// can not be buffered without bound.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use icaparse::{ChunkedDecoder, Error, ParserConfig};
+    ///
+    /// let mut config = ParserConfig::default();
+    /// config.max_chunk_size(Some(1024)).max_chunk_line_len(Some(8));
+    /// let mut decoder = ChunkedDecoder::new().with_config(&config);
+    /// assert_eq!(decoder.decode(b"401\r\n"), Err(Error::ChunkTooLarge));
+    /// let mut decoder = ChunkedDecoder::new().with_config(&config);
+    /// assert_eq!(decoder.decode(b"4;aaaaaaaa"), Err(Error::LineTooLong));
+    /// ```
+    #[inline]
+    pub fn with_config(mut self, config: &ParserConfig) -> ChunkedDecoder {
+        self.max_size = config.max_chunk_size;
+        self.max_line_len = config.max_chunk_line_len;
+        self
+    }
+
+    /// Returns true once the last chunk has been decoded.
+    #[inline]
+    pub fn is_done(&self) -> bool {
+        self.state == State::Done
+    }
+
+    /// Returns true if a preview has ended without containing the whole body.
+    #[inline]
+    pub fn is_preview_end(&self) -> bool {
+        self.state == State::PreviewEnd
+    }
+
+    /// Continues decoding the body after the end of a preview.
+    ///
+    /// Call this once the server has replied `ICAP/1.0 100 Continue`. The
+    /// decoder then expects the rest of the body as ordinary chunks. Does
+    /// nothing unless `is_preview_end` returns true.
+    #[inline]
+    pub fn resume(&mut self) {
+        if self.state == State::PreviewEnd {
+            self.state = State::Size;
+            self.preview = false;
+        }
+    }
+
+    /// Decode the next piece of the body from the start of `buf`.
+    ///
+    /// Once the last chunk has been returned, every further call returns an
+    /// empty `Chunk::Last` without consuming anything. After the end of a
+    /// preview, `Status::Partial` is returned until `resume` is called.
+    ///
+    /// Returns `Error::Chunk` if the body is not validly chunked.
+    pub fn decode<'a>(&mut self, buf: &'a [u8]) -> Result<(usize, Chunk<'a>)> {
+        let mut pos = 0;
+        let mut state = self.state;
+        loop {
+            match state {
+                State::Size => {
+                    let (len, size, extensions) = complete!(self.parse_size_line(&buf[pos..]));
+                    pos += len;
+                    if size == 0 {
+                        pos += complete!(parse_trailer(&buf[pos..], self.max_line_len));
+                        if self.preview && !extensions.ieof() {
+                            self.state = State::PreviewEnd;
+                            return Ok(Status::Complete((pos, Chunk::PreviewEnd { extensions })));
+                        }
+                        self.state = State::Done;
+                        return Ok(Status::Complete((pos, Chunk::Last { extensions })));
+                    }
+                    self.state = State::Data(size);
+                    return Ok(Status::Complete((pos, Chunk::Head { size, extensions })));
+                },
+                State::Data(remaining) => {
+                    if buf.is_empty() {
+                        return Ok(Status::Partial);
+                    }
+                    let len = cmp::min(remaining, buf.len() as u64) as usize;
+                    self.state = if remaining == len as u64 {
+                        State::DataEnd
+                    } else {
+                        State::Data(remaining - len as u64)
+                    };
+                    return Ok(Status::Complete((len, Chunk::Data(&buf[..len]))));
+                },
+                State::DataEnd => {
+                    // The line break after the chunk data is only consumed
+                    // together with the next size line.
+                    pos += complete!(parse_crlf(&buf[pos..]));
+                    state = State::Size;
+                },
+                State::PreviewEnd => return Ok(Status::Partial),
+                State::Done => {
+                    return Ok(Status::Complete((0, Chunk::Last { extensions: ChunkExtensions::new(&[]) })));
+                },
+            }
+        }
+    }
+
+    /// Parses a chunk size line, checking it against the limits.
+    pub(crate) fn parse_size_line<'a>(&self, buf: &'a [u8]) -> Result<(usize, u64, ChunkExtensions<'a>)> {
+        match parse_chunk_head(buf) {
+            Ok(Status::Complete((len, size, extensions))) => {
+                check_line(&buf[..len], self.max_line_len)?;
+                if self.max_size.is_some_and(|max| size > max) {
+                    return Err(Error::ChunkTooLarge);
+                }
+                Ok(Status::Complete((len, size, extensions)))
+            },
+            Ok(Status::Partial) => {
+                check_line(buf, self.max_line_len)?;
+                Ok(Status::Partial)
+            },
+            Err(InvalidChunkSize) => Err(Error::Chunk),
+        }
+    }
+}
+
+/// Checks the length of a line, or of the part of it received so far, not
+/// counting its line break.
+#[inline]
+fn check_line(line: &[u8], max_line_len: Option<usize>) -> ::core::result::Result<(), Error> {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    if max_line_len.is_some_and(|max| line.len() > max) {
+        Err(Error::LineTooLong)
+    } else {
+        Ok(())
+    }
+}
+
+impl Default for ChunkedDecoder {
+    fn default() -> ChunkedDecoder {
+        ChunkedDecoder::new()
+    }
+}
+
+#[inline]
+fn parse_crlf(buf: &[u8]) -> Result<usize> {
+    let mut bytes = Bytes::new(buf);
+    expect!(bytes.next() == b'\r' => Err(Error::Chunk));
+    expect!(bytes.next() == b'\n' => Err(Error::Chunk));
+    Ok(Status::Complete(bytes.pos()))
+}
+
+/// Skips the trailer fields following the last chunk, up to and including the
+/// empty line that ends the body.
+#[inline]
+fn parse_trailer(buf: &[u8], max_line_len: Option<usize>) -> Result<usize> {
+    let mut bytes = Bytes::new(buf);
+    loop {
+        let start = bytes.pos();
+        match next!(bytes) {
+            b'\r' => {
+                expect!(bytes.next() == b'\n' => Err(Error::Chunk));
+                return Ok(Status::Complete(bytes.pos()));
+            },
+            b'\n' => return Ok(Status::Complete(bytes.pos())),
+            _ => loop {
+                check_line(&buf[start..bytes.pos()], max_line_len)?;
+                if next!(bytes) == b'\n' {
+                    break;
+                }
+            },
+        }
+    }
+}
@@ -0,0 +1,470 @@
+use std::{error, fmt, io};
+use std::ops::Range;
+use std::string::String;
+use std::vec::Vec;
+
+use bytes::{Bytes, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+
+use {Connection, Error, Event, Header, ParserConfig, RequestBuilder, ResponseBuilder, SectionType, Sections,
+     Status, EMPTY_HEADER};
+
+/// An owned ICAP header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OwnedHeader {
+    /// The name of the header.
+    pub name: String,
+    /// The value of the header.
+    pub value: Bytes,
+}
+
+/// The owned head of an ICAP request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RequestHead {
+    /// The request method, such as `RESPMOD`.
+    pub method: String,
+    /// The request path, such as `icap://icap.example.org/filter`.
+    pub path: String,
+    /// The minor version, `0` for `ICAP/1.0`.
+    pub version: u8,
+    /// The request headers.
+    pub headers: Vec<OwnedHeader>,
+}
+
+/// The owned head of an ICAP response.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ResponseHead {
+    /// The minor version, `0` for `ICAP/1.0`.
+    pub version: u8,
+    /// The response code, such as `200`.
+    pub code: u16,
+    /// The response reason-phrase, such as `OK`.
+    pub reason: String,
+    /// The response headers.
+    pub headers: Vec<OwnedHeader>,
+}
+
+/// A piece of an ICAP message, as decoded by `ServerCodec` and `ClientCodec`.
+///
+/// These mirror the `Event`s of a `Connection`, with owned data.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Frame<H> {
+    /// The head of a message.
+    Head(H),
+    /// An encapsulated HTTP header section, such as `req-hdr`.
+    Section(SectionType, Bytes),
+    /// Decoded bytes of the encapsulated body.
+    Body(Bytes),
+    /// The end of a preview that did not contain the whole body.
+    PreviewEnd,
+    /// The end of the message.
+    End,
+}
+
+/// An error from `ServerCodec` or `ClientCodec`.
+#[derive(Debug)]
+pub enum CodecError {
+    /// Reading or writing the underlying transport failed.
+    Io(io::Error),
+    /// The message is invalid.
+    Parse(Error),
+    /// The head of a message, along with its encapsulated HTTP headers, is
+    /// longer than `Limits::max_head_len`.
+    HeadTooLarge,
+    /// The encapsulated body is longer than `Limits::max_body_len`.
+    BodyTooLarge,
+    /// More than `Limits::max_head_len` bytes arrived after the end of a
+    /// preview, before it was resumed or ended.
+    PreviewEndTooLarge,
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CodecError::Io(ref err) => err.fmt(f),
+            CodecError::Parse(ref err) => err.fmt(f),
+            CodecError::HeadTooLarge => f.write_str("message head too large"),
+            CodecError::BodyTooLarge => f.write_str("message body too large"),
+            CodecError::PreviewEndTooLarge => f.write_str("too many bytes after preview end"),
+        }
+    }
+}
+
+impl error::Error for CodecError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            CodecError::Io(ref err) => Some(err),
+            CodecError::Parse(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for CodecError {
+    fn from(err: io::Error) -> CodecError {
+        CodecError::Io(err)
+    }
+}
+
+impl From<Error> for CodecError {
+    fn from(err: Error) -> CodecError {
+        CodecError::Parse(err)
+    }
+}
+
+/// Size limits enforced while decoding.
+///
+/// These bound what the codec buffers. Limits on the parsed message itself,
+/// such as the longest header line, are set with a `ParserConfig`; see
+/// `ServerCodec::with_config`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Limits {
+    max_head_len: usize,
+    max_headers: usize,
+    max_body_len: Option<u64>,
+}
+
+impl Limits {
+    /// Sets the longest head allowed, including the encapsulated HTTP
+    /// headers, which are buffered along with it. Defaults to 64 KiB.
+    ///
+    /// This also bounds the bytes buffered after the end of a preview, which
+    /// may hold the head of the next message.
+    #[inline]
+    pub fn max_head_len(&mut self, len: usize) -> &mut Limits {
+        self.max_head_len = len;
+        self
+    }
+
+    /// Sets the most ICAP headers allowed in a head. Defaults to 64.
+    #[inline]
+    pub fn max_headers(&mut self, count: usize) -> &mut Limits {
+        self.max_headers = count;
+        self
+    }
+
+    /// Sets the longest decoded body allowed in a message, or `None` for no
+    /// limit, the default.
+    #[inline]
+    pub fn max_body_len(&mut self, len: Option<u64>) -> &mut Limits {
+        self.max_body_len = len;
+        self
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            max_head_len: 64 * 1024,
+            max_headers: 64,
+            max_body_len: None,
+        }
+    }
+}
+
+/// The longest chunk size or trailer line `ServerCodec::new` and
+/// `ClientCodec::new` allow.
+const DEFAULT_MAX_CHUNK_LINE_LEN: usize = 4 * 1024;
+
+/// A decoded event, with its data given as ranges of the consumed bytes.
+enum Piece {
+    Head(Head),
+    Section(SectionType, Range<usize>),
+    Body(Range<usize>),
+    PreviewEnd,
+    End,
+    Closed,
+}
+
+/// The head of a message, for either side of a connection.
+enum Head {
+    Request(RequestHead),
+    Response(ResponseHead),
+}
+
+/// Frames messages for either side of a connection.
+#[derive(Debug)]
+struct Framer {
+    conn: Connection,
+    limits: Limits,
+    body_len: u64,
+}
+
+impl Framer {
+    fn new(conn: Connection) -> Framer {
+        Framer {
+            conn,
+            limits: Limits::default(),
+            body_len: 0,
+        }
+    }
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Frame<Head>>, CodecError> {
+        // the headers are only used when parsing a head
+        let reading_head = self.conn.is_reading_head();
+        let mut headers = if reading_head {
+            vec![EMPTY_HEADER; self.limits.max_headers]
+        } else {
+            Vec::new()
+        };
+        let (len, piece) = {
+            let buf = &src[..];
+            let (len, event) = match self.conn.poll(buf, &mut headers)? {
+                Status::Complete(v) => v,
+                Status::Partial => {
+                    if reading_head && buf.len() > self.limits.max_head_len {
+                        return Err(CodecError::HeadTooLarge);
+                    }
+                    if self.conn.is_preview_end() && buf.len() > self.limits.max_head_len {
+                        return Err(CodecError::PreviewEndTooLarge);
+                    }
+                    return Ok(None);
+                },
+            };
+            // the encapsulated HTTP headers are buffered along with the head
+            let head_len = |sections: &Option<Sections>| len + sections.iter()
+                .flat_map(|sections| sections.iter())
+                .filter(|section| !section.name.is_body())
+                .map(|section| section.value.len())
+                .sum::<usize>();
+            let head_len = match event {
+                Event::Request(ref req) => head_len(&req.sections),
+                Event::Response(ref res) => head_len(&res.sections),
+                _ => 0,
+            };
+            if head_len > self.limits.max_head_len {
+                return Err(CodecError::HeadTooLarge);
+            }
+            let range = |data: &[u8]| {
+                let start = data.as_ptr() as usize - buf.as_ptr() as usize;
+                start..start + data.len()
+            };
+            let piece = match event {
+                Event::Request(req) => Piece::Head(Head::Request(RequestHead {
+                    method: req.method.unwrap_or("").into(),
+                    path: req.path.unwrap_or("").into(),
+                    version: req.version.unwrap_or(0),
+                    headers: owned_headers(req.headers),
+                })),
+                Event::Response(res) => Piece::Head(Head::Response(ResponseHead {
+                    version: res.version.unwrap_or(0),
+                    code: res.code.unwrap_or(0),
+                    reason: res.reason.unwrap_or("").into(),
+                    headers: owned_headers(res.headers),
+                })),
+                Event::Header(section) => Piece::Section(section.name, range(section.value)),
+                Event::Body(data) => Piece::Body(range(data)),
+                Event::PreviewEnd => Piece::PreviewEnd,
+                Event::End => Piece::End,
+                Event::Closed => Piece::Closed,
+            };
+            (len, piece)
+        };
+
+        match piece {
+            Piece::Head(_) => self.body_len = 0,
+            Piece::Body(ref range) => {
+                self.body_len += range.len() as u64;
+                if self.limits.max_body_len.is_some_and(|max| self.body_len > max) {
+                    return Err(CodecError::BodyTooLarge);
+                }
+            },
+            _ => {},
+        }
+
+        // the sections and body are sliced out of the consumed bytes, without
+        // copying them
+        let consumed = src.split_to(len).freeze();
+        Ok(match piece {
+            Piece::Head(head) => Some(Frame::Head(head)),
+            Piece::Section(name, range) => Some(Frame::Section(name, consumed.slice(range))),
+            Piece::Body(range) => Some(Frame::Body(consumed.slice(range))),
+            Piece::PreviewEnd => Some(Frame::PreviewEnd),
+            Piece::End => Some(Frame::End),
+            Piece::Closed => {
+                // nothing more is parsed once the connection is closed, so
+                // bytes sent after it are dropped rather than buffered
+                src.clear();
+                None
+            },
+        })
+    }
+}
+
+fn owned_headers(headers: &[Header]) -> Vec<OwnedHeader> {
+    headers.iter().map(|h| OwnedHeader {
+        name: h.name.into(),
+        value: Bytes::copy_from_slice(h.value),
+    }).collect()
+}
+
+impl<H> Frame<H> {
+    fn map_head<T, F: FnOnce(H) -> T>(self, f: F) -> Frame<T> {
+        match self {
+            Frame::Head(head) => Frame::Head(f(head)),
+            Frame::Section(name, value) => Frame::Section(name, value),
+            Frame::Body(data) => Frame::Body(data),
+            Frame::PreviewEnd => Frame::PreviewEnd,
+            Frame::End => Frame::End,
+        }
+    }
+}
+
+/// Writes a message with `encode`, which fails unless given `len` bytes.
+fn encode_into<F>(dst: &mut BytesMut, len: usize, encode: F) -> Result<(), CodecError>
+        where F: FnOnce(&mut [u8]) -> ::core::result::Result<usize, ::EncodeError> {
+    let start = dst.len();
+    dst.resize(start + len, 0);
+    match encode(&mut dst[start..]) {
+        Ok(_) => Ok(()),
+        Err(err) => {
+            dst.truncate(start);
+            let kind = match err {
+                ::EncodeError::Invalid(_) => io::ErrorKind::InvalidInput,
+                ::EncodeError::BufferTooSmall => io::ErrorKind::Other,
+            };
+            Err(CodecError::Io(io::Error::new(kind, err)))
+        },
+    }
+}
+
+macro_rules! codec {
+    ($(#[$attr:meta])* $name:ident, $conn:ident, $head:ident, $variant:ident,
+     $(#[$encode_attr:meta])* $builder:ident) => {
+        $(#[$attr])*
+        #[derive(Debug)]
+        pub struct $name {
+            framer: Framer,
+        }
+
+        impl $name {
+            /// Creates a codec with the default `Limits` and `ParserConfig`,
+            /// except that chunk size and trailer lines are limited to 4 KiB.
+            ///
+            /// Bytes are only buffered within the body until such a line is
+            /// complete, so this bounds the buffer once the head has been
+            /// read.
+            #[inline]
+            pub fn new() -> $name {
+                let mut config = ParserConfig::default();
+                config.max_chunk_line_len(Some(DEFAULT_MAX_CHUNK_LINE_LEN));
+                $name::with_config(config)
+            }
+
+            /// Creates a codec parsing messages with the given configuration,
+            /// and the default `Limits`.
+            #[inline]
+            pub fn with_config(config: ParserConfig) -> $name {
+                $name { framer: Framer::new(Connection::$conn().with_config(config)) }
+            }
+
+            /// The size limits enforced while decoding.
+            #[inline]
+            pub fn limits(&mut self) -> &mut Limits {
+                &mut self.framer.limits
+            }
+
+            /// The state of the connection.
+            #[inline]
+            pub fn connection(&self) -> &Connection {
+                &self.framer.conn
+            }
+
+            /// Continues with the rest of the body after a preview.
+            ///
+            /// See `Connection::resume_preview`.
+            #[inline]
+            pub fn resume_preview(&mut self) {
+                self.framer.conn.resume_preview();
+            }
+
+            /// Ends the message after a preview, without reading the rest of
+            /// the body.
+            ///
+            /// See `Connection::end_preview`.
+            #[inline]
+            pub fn end_preview(&mut self) {
+                self.framer.conn.end_preview();
+            }
+        }
+
+        impl Default for $name {
+            fn default() -> $name {
+                $name::new()
+            }
+        }
+
+        impl Decoder for $name {
+            type Item = Frame<$head>;
+            type Error = CodecError;
+
+            fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Frame<$head>>, CodecError> {
+                Ok(self.framer.decode(src)?.map(|frame| frame.map_head(|head| match head {
+                    Head::$variant(head) => head,
+                    _ => unreachable!(),
+                })))
+            }
+        }
+
+        $(#[$encode_attr])*
+        impl<'a> Encoder<$builder<'a>> for $name {
+            type Error = CodecError;
+
+            fn encode(&mut self, item: $builder<'a>, dst: &mut BytesMut) -> Result<(), CodecError> {
+                encode_into(dst, item.encoded_len(), |buf| item.encode(buf))
+            }
+        }
+    }
+}
+
+codec! {
+    /// A `tokio-util` codec for the server side of an ICAP connection.
+    ///
+    /// It decodes requests as `Frame`s, and encodes responses from a
+    /// `ResponseBuilder`. Every frame of the encapsulated data is yielded as
+    /// soon as it is complete, so bodies are streamed rather than buffered.
+    ///
+    /// Requires the `codec` feature.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// extern crate bytes;
+    /// extern crate icaparse;
+    /// extern crate tokio_util;
+    ///
+    /// use bytes::BytesMut;
+    /// use icaparse::{Frame, ServerCodec};
+    /// use tokio_util::codec::Decoder;
+    ///
+    /// # fn main() {
+    /// let mut codec = ServerCodec::new();
+    /// let mut buf = BytesMut::from(&b"REQMOD icap://icap.example.org/filter ICAP/1.0\r\n\
+    /// Encapsulated: req-body=0\r\n\r\n\
+    /// 4\r\nRust\r\n0\r\n\r\n"[..]);
+    ///
+    /// match codec.decode(&mut buf).unwrap() {
+    ///     Some(Frame::Head(head)) => assert_eq!(head.method, "REQMOD"),
+    ///     _ => unreachable!(),
+    /// }
+    /// assert_eq!(codec.decode(&mut buf).unwrap(), Some(Frame::Body(b"Rust"[..].into())));
+    /// assert_eq!(codec.decode(&mut buf).unwrap(), Some(Frame::End));
+    /// assert_eq!(codec.decode(&mut buf).unwrap(), None);
+    /// # }
+    /// ```
+    ServerCodec, server, RequestHead, Request,
+    /// Encodes a response.
+    ResponseBuilder
+}
+
+codec! {
+    /// A `tokio-util` codec for the client side of an ICAP connection.
+    ///
+    /// It decodes responses as `Frame`s, and encodes requests from a
+    /// `RequestBuilder`. See `ServerCodec` for an example.
+    ///
+    /// Requires the `codec` feature.
+    ClientCodec, client, ResponseHead, Response,
+    /// Encodes a request.
+    RequestBuilder
+}
@@ -0,0 +1,315 @@
+use {find_header, trim_ows, Chunk, ChunkedDecoder, EncapsulatedHeader, Error, Header,
+     ParserConfig, Request, Response, Result, Section, SectionType, Status};
+
+/// Which side of an ICAP connection is being parsed.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Role {
+    /// Parsing requests, as an ICAP server does.
+    Server,
+    /// Parsing responses, as an ICAP client does.
+    Client,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum State {
+    Head,
+    Sections(EncapsulatedHeader, usize, ChunkedDecoder),
+    Body(ChunkedDecoder),
+    PreviewEnd(ChunkedDecoder),
+    End,
+    Closed,
+}
+
+/// An event produced by a `Connection`.
+#[derive(Debug, PartialEq)]
+pub enum Event<'h, 'b: 'h> {
+    /// The head of a request, when parsing the server side of a connection.
+    Request(Request<'h, 'b>),
+    /// The head of a response, when parsing the client side of a connection.
+    Response(Response<'h, 'b>),
+    /// An encapsulated HTTP header section, such as `req-hdr`.
+    Header(Section<'b>),
+    /// Decoded bytes of the encapsulated body.
+    Body(&'b [u8]),
+    /// The end of a preview that did not contain the whole body.
+    ///
+    /// See `Connection::resume_preview` and `Connection::end_preview`.
+    PreviewEnd,
+    /// The end of the message.
+    End,
+    /// The connection was closed after the last message, and no more
+    /// messages will be parsed.
+    Closed,
+}
+
+/// A sans-IO state machine for one side of an ICAP connection.
+///
+/// The connection does not read or buffer anything itself, so it can be
+/// driven by blocking sockets, an async runtime, or a test harness alike.
+/// Each call to `poll` reads from the start of the given buffer and returns
+/// how many bytes were consumed, along with the `Event` found there. The
+/// caller should drop the consumed bytes and call `poll` again with what
+/// remains, reading more data whenever `Status::Partial` is returned.
+///
+/// A message produces its head first, then each encapsulated HTTP header
+/// section, then its decoded body, and finally `Event::End`. Messages can be
+/// pipelined on the same connection, until one of them carries a
+/// `Connection: close` header.
+///
+/// # Example
+///
+/// ```
+/// use icaparse::{Connection, Event, Status};
+///
+/// let mut buf = &b"REQMOD icap://icap.example.org/filter ICAP/1.0\r\n\
+/// Encapsulated: req-hdr=0, req-body=38\r\n\
+/// \r\n\
+/// POST / HTTP/1.1\r\nHost: example.org\r\n\r\n\
+/// 4\r\nRust\r\n0\r\n\r\n"[..];
+///
+/// let mut conn = Connection::server();
+/// let mut body = Vec::new();
+/// loop {
+///     let mut headers = [icaparse::EMPTY_HEADER; 16];
+///     let (n, event) = match conn.poll(buf, &mut headers).unwrap() {
+///         Status::Complete(v) => v,
+///         Status::Partial => unreachable!("read more bytes into the buffer"),
+///     };
+///     buf = &buf[n..];
+///     match event {
+///         Event::Request(req) => assert_eq!(req.method, Some("REQMOD")),
+///         Event::Body(data) => body.extend_from_slice(data),
+///         Event::End => break,
+///         _ => {},
+///     }
+/// }
+/// assert_eq!(body, b"Rust");
+/// assert!(buf.is_empty());
+/// ```
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Connection {
+    role: Role,
+    state: State,
+    keep_alive: bool,
+    config: ParserConfig,
+}
+
+impl Connection {
+    /// Creates a connection parsing ICAP requests, as an ICAP server does.
+    #[inline]
+    pub fn server() -> Connection {
+        Connection::new(Role::Server)
+    }
+
+    /// Creates a connection parsing ICAP responses, as an ICAP client does.
+    #[inline]
+    pub fn client() -> Connection {
+        Connection::new(Role::Client)
+    }
+
+    #[inline]
+    fn new(role: Role) -> Connection {
+        Connection {
+            role,
+            state: State::Head,
+            keep_alive: true,
+            config: ParserConfig::default(),
+        }
+    }
+
+    /// Parses every message with the given configuration, instead of the
+    /// default one.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut config = icaparse::ParserConfig::default();
+    /// config.max_line_len(Some(1024));
+    /// let conn = icaparse::Connection::server().with_config(config);
+    /// assert_eq!(conn.config(), &config);
+    /// ```
+    #[inline]
+    pub fn with_config(mut self, config: ParserConfig) -> Connection {
+        self.config = config;
+        self
+    }
+
+    /// The configuration messages are parsed with.
+    #[inline]
+    pub fn config(&self) -> &ParserConfig {
+        &self.config
+    }
+
+    /// Returns true if the connection stays open after the current message.
+    #[inline]
+    pub fn keep_alive(&self) -> bool {
+        self.keep_alive
+    }
+
+    /// Returns true once the connection has been closed.
+    #[inline]
+    pub fn is_closed(&self) -> bool {
+        self.state == State::Closed
+    }
+
+    /// Returns true if a preview has ended, and the rest of the body will only
+    /// be sent after `ICAP/1.0 100 Continue`.
+    #[inline]
+    pub fn is_preview_end(&self) -> bool {
+        matches!(self.state, State::PreviewEnd(..))
+    }
+
+    /// Continues with the rest of the body after a preview.
+    ///
+    /// Call this once `ICAP/1.0 100 Continue` has been sent. Does nothing
+    /// unless `is_preview_end` returns true.
+    pub fn resume_preview(&mut self) {
+        if let State::PreviewEnd(mut decoder) = self.state {
+            decoder.resume();
+            self.state = State::Body(decoder);
+        }
+    }
+
+    /// Ends the message after a preview, without reading the rest of the body.
+    ///
+    /// Call this when replying with a final response instead of
+    /// `ICAP/1.0 100 Continue`, as the client will not send the rest of the
+    /// body. The next call to `poll` returns `Event::End`. Does nothing unless
+    /// `is_preview_end` returns true.
+    pub fn end_preview(&mut self) {
+        if let State::PreviewEnd(..) = self.state {
+            self.state = State::End;
+        }
+    }
+
+    /// Returns true while waiting for the head of a message.
+    #[cfg(feature = "codec")]
+    #[inline]
+    pub(crate) fn is_reading_head(&self) -> bool {
+        self.state == State::Head
+    }
+
+    /// Parse the next event from the start of `buf`.
+    ///
+    /// The `headers` are only used when parsing the head of a message.
+    /// Returns `Status::Partial` if more bytes are needed, which is also the
+    /// case after the end of a preview, until `resume_preview` or
+    /// `end_preview` is called.
+    pub fn poll<'h, 'b>(&mut self, buf: &'b [u8], headers: &'h mut [Header<'b>])
+            -> Result<(usize, Event<'h, 'b>)> {
+        match self.state {
+            State::Head => {
+                // the body is decoded as it arrives, rather than scanned along
+                // with the head
+                let mut config = self.config;
+                config.skip_body(true);
+                match self.role {
+                    Role::Server => {
+                        let mut req = Request::new(headers);
+                        complete!(config.parse_request(&mut req, buf));
+                        let preview = req.preview()?.is_some();
+                        let len = self.start_message(req.headers, req.encapsulated_offset(), preview)?;
+                        Ok(Status::Complete((len, Event::Request(req))))
+                    },
+                    Role::Client => {
+                        let mut res = Response::new(headers);
+                        complete!(config.parse_response(&mut res, buf));
+                        let len = self.start_message(res.headers, res.encapsulated_offset(), false)?;
+                        Ok(Status::Complete((len, Event::Response(res))))
+                    },
+                }
+            },
+            State::Sections(header, i, decoder) => {
+                let section = header.as_slice()[i];
+                let len = header.as_slice()[i + 1].start() - section.start();
+                if buf.len() < len {
+                    return Ok(Status::Partial);
+                }
+                self.state = if i + 2 < header.len() {
+                    State::Sections(header, i + 1, decoder)
+                } else {
+                    body_state(header.body().name(), decoder)
+                };
+                Ok(Status::Complete((len, Event::Header(Section {
+                    name: section.name(),
+                    offset: section.start(),
+                    value: &buf[..len],
+                }))))
+            },
+            State::Body(decoder) => {
+                // chunk size lines are only consumed along with the data or
+                // end that follows them
+                let mut decoder = decoder;
+                let mut pos = 0;
+                loop {
+                    let (len, chunk) = complete!(decoder.decode(&buf[pos..]));
+                    pos += len;
+                    match chunk {
+                        Chunk::Head { .. } => continue,
+                        Chunk::Data(data) => {
+                            self.state = State::Body(decoder);
+                            return Ok(Status::Complete((pos, Event::Body(data))));
+                        },
+                        Chunk::PreviewEnd { .. } => {
+                            self.state = State::PreviewEnd(decoder);
+                            return Ok(Status::Complete((pos, Event::PreviewEnd)));
+                        },
+                        Chunk::Last { .. } => {
+                            self.end_message();
+                            return Ok(Status::Complete((pos, Event::End)));
+                        },
+                    }
+                }
+            },
+            State::PreviewEnd(..) => Ok(Status::Partial),
+            State::End => {
+                self.end_message();
+                Ok(Status::Complete((0, Event::End)))
+            },
+            State::Closed => Ok(Status::Complete((0, Event::Closed))),
+        }
+    }
+
+    /// Sets up the state for the encapsulated data of a message whose head
+    /// was parsed, returning the length of the head.
+    fn start_message(&mut self, headers: &[Header], offset: Option<usize>, preview: bool)
+            -> ::core::result::Result<usize, Error> {
+        if let Some(h) = find_header(headers, "Connection") {
+            if has_token(h.value, b"close") {
+                self.keep_alive = false;
+            }
+        }
+        let encapsulated = match find_header(headers, "Encapsulated") {
+            Some(h) => EncapsulatedHeader::parse(h.value)?,
+            None => {
+                self.state = State::End;
+                return Ok(offset.unwrap_or(0));
+            },
+        };
+        let decoder = if preview { ChunkedDecoder::preview() } else { ChunkedDecoder::new() };
+        let decoder = decoder.with_config(&self.config);
+        self.state = if encapsulated.len() > 1 {
+            State::Sections(encapsulated, 0, decoder)
+        } else {
+            body_state(encapsulated.body().name(), decoder)
+        };
+        Ok(offset.unwrap_or(0))
+    }
+
+    fn end_message(&mut self) {
+        self.state = if self.keep_alive { State::Head } else { State::Closed };
+    }
+}
+
+#[inline]
+fn body_state(name: SectionType, decoder: ChunkedDecoder) -> State {
+    match name {
+        SectionType::NullBody => State::End,
+        _ => State::Body(decoder),
+    }
+}
+
+/// Checks a comma separated header value for a token, ignoring case.
+fn has_token(value: &[u8], token: &[u8]) -> bool {
+    value.split(|&b| b == b',').any(|item| trim_ows(item).eq_ignore_ascii_case(token))
+}
@@ -0,0 +1,180 @@
+use core::{fmt, str};
+
+use {Error, Header};
+
+/// A parsing `Error`, along with where in the buffer it was found.
+///
+/// Returned by `Request::parse_detailed` and `Response::parse_detailed`.
+/// Finding the context means parsing the buffer again a few times, so it is
+/// only done once parsing has failed; the plain `parse` methods keep
+/// returning the cheap `Error`.
+///
+/// # Example
+///
+/// ```
+/// let buf = b"REQMOD / ICAP/1.0\r\nHost: example.org\r\nX-Bad: a\x01b\r\n\r\n";
+/// let mut headers = [icaparse::EMPTY_HEADER; 4];
+/// let mut req = icaparse::Request::new(&mut headers);
+/// let err = req.parse_detailed(buf).unwrap_err();
+/// assert_eq!(err.kind(), icaparse::Error::HeaderValue);
+/// assert_eq!(err.offset(), 46);
+/// assert_eq!(err.byte(), Some(0x01));
+/// assert_eq!(err.header_index(), Some(1));
+/// assert_eq!(err.header_name(), Some(&b"X-Bad"[..]));
+/// ```
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ParseError<'b> {
+    kind: Error,
+    offset: usize,
+    byte: Option<u8>,
+    header: Option<(usize, &'b [u8])>,
+}
+
+impl<'b> ParseError<'b> {
+    /// Locates an error found by `parse`, given a function telling whether
+    /// parsing a prefix of `buf` fails, and whether headers were parsed in
+    /// `ParseMode::Lenient`.
+    pub(crate) fn locate<F>(kind: Error, buf: &'b [u8], lenient: bool, mut fails: F) -> ParseError<'b>
+            where F: FnMut(&'b [u8]) -> bool {
+        // parsing is incremental, so once a prefix fails every longer one
+        // does too
+        let (mut lo, mut hi) = (0, buf.len());
+        while lo + 1 < hi {
+            let mid = lo + (hi - lo) / 2;
+            if fails(&buf[..mid]) {
+                hi = mid;
+            } else {
+                lo = mid;
+            }
+        }
+        let offset = hi.saturating_sub(1);
+        ParseError {
+            kind,
+            offset,
+            byte: buf.get(offset).cloned(),
+            header: header_at(buf, offset, kind == Error::HeaderName, lenient),
+        }
+    }
+
+    /// Points errors found in the `Encapsulated` header once the head is
+    /// complete at that header.
+    pub(crate) fn with_headers(mut self, headers: &[Header<'b>]) -> ParseError<'b> {
+        if let Error::Encapsulated | Error::SectionNotAllowed = self.kind {
+            self.header = headers.iter()
+                .position(|h| h.name.eq_ignore_ascii_case("Encapsulated"))
+                .map(|i| (i, headers[i].name.as_bytes()));
+        }
+        self
+    }
+
+    /// The kind of error.
+    #[inline]
+    pub fn kind(&self) -> Error {
+        self.kind
+    }
+
+    /// The offset in the parsed buffer of the byte at which the error was
+    /// found.
+    ///
+    /// Fixed-width fields, such as the ICAP version, are checked as a whole,
+    /// so their errors may point a few bytes past the offending one.
+    /// Errors that can only be found once the head is complete, such as
+    /// `Error::MissingEncapsulated`, point at the end of the head.
+    #[inline]
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// The byte at which the error was found, if the buffer was not empty.
+    #[inline]
+    pub fn byte(&self) -> Option<u8> {
+        self.byte
+    }
+
+    /// The index of the header in which the error was found.
+    #[inline]
+    pub fn header_index(&self) -> Option<usize> {
+        self.header.map(|(index, _)| index)
+    }
+
+    /// The name of the header in which the error was found, up to the
+    /// offending byte if the name itself is invalid.
+    #[inline]
+    pub fn header_name(&self) -> Option<&'b [u8]> {
+        self.header.map(|(_, name)| name)
+    }
+}
+
+impl<'b> From<ParseError<'b>> for Error {
+    #[inline]
+    fn from(err: ParseError<'b>) -> Error {
+        err.kind
+    }
+}
+
+impl<'b> fmt::Display for ParseError<'b> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at byte {}", self.kind, self.offset)?;
+        if let Some(byte) = self.byte {
+            write!(f, " ({:#04x})", byte)?;
+        }
+        if let Some((index, name)) = self.header {
+            write!(f, " in header {}", index)?;
+            if let Ok(name) = str::from_utf8(name) {
+                write!(f, " {:?}", name)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'b> ::std::error::Error for ParseError<'b> {}
+
+/// Finds the header of the head containing `offset`, if any.
+///
+/// If `in_name` is true, the name stops at `offset`, as it is the offending
+/// byte. If `lenient` is true, headers are counted as `ParseMode::Lenient`
+/// parses them: a line starting with whitespace continues the header before
+/// it, and whitespace may come between a name and its colon.
+fn header_at(buf: &[u8], offset: usize, in_name: bool, lenient: bool) -> Option<(usize, &[u8])> {
+    let mut lines = buf.split(|&b| b == b'\n');
+    let mut start = 0;
+    // skip the empty lines before the start line, and the start line itself
+    loop {
+        let line = lines.next()?;
+        start += line.len() + 1;
+        if offset < start {
+            return None;
+        }
+        if line != b"\r" && !line.is_empty() {
+            break;
+        }
+    }
+    // the index, start and first line of the header being read
+    let mut header: Option<(usize, usize, &[u8])> = None;
+    for line in lines {
+        let end = start + line.len() + 1;
+        if line == b"\r" || line.is_empty() {
+            // the end of the head
+            return None;
+        }
+        let folded = lenient && header.is_some() && (line[0] == b' ' || line[0] == b'\t');
+        if !folded {
+            let index = header.map_or(0, |(index, _, _)| index + 1);
+            header = Some((index, start, line));
+        }
+        if offset < end {
+            let (index, header_start, first) = header?;
+            let mut name_end = first.iter()
+                .position(|&b| b == b':' || (lenient && (b == b' ' || b == b'\t')))
+                .unwrap_or(first.len());
+            if in_name && !folded {
+                name_end = name_end.min(offset - header_start);
+            }
+            return Some((index, &first[..name_end]));
+        }
+        start = end;
+    }
+    None
+}
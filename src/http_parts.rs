@@ -0,0 +1,222 @@
+use std::{error, fmt};
+use std::vec::Vec;
+
+use http::{self, header, request, response, Method, StatusCode, Version};
+use httparse;
+
+use Sections;
+use SectionType;
+
+/// The most headers an encapsulated HTTP header section may have to be
+/// converted.
+const MAX_HTTP_HEADERS: usize = 100;
+
+/// An error converting an encapsulated HTTP header section into `http` types.
+#[derive(Debug)]
+pub enum HttpError {
+    /// The section is not a valid HTTP head.
+    Parse(httparse::Error),
+    /// The section does not hold a complete HTTP head.
+    Incomplete,
+    /// The section has more than 100 headers, the most that are converted.
+    TooManyHeaders,
+    /// The HTTP head is valid, but could not be represented, such as a
+    /// status code out of range.
+    Http(http::Error),
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            HttpError::Parse(ref err) => write!(f, "invalid encapsulated HTTP head: {}", err),
+            HttpError::Incomplete => f.write_str("incomplete encapsulated HTTP head"),
+            HttpError::TooManyHeaders => f.write_str("too many headers in encapsulated HTTP head"),
+            HttpError::Http(ref err) => write!(f, "invalid encapsulated HTTP head: {}", err),
+        }
+    }
+}
+
+impl error::Error for HttpError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            HttpError::Parse(ref err) => Some(err),
+            HttpError::Http(ref err) => Some(err),
+            HttpError::Incomplete | HttpError::TooManyHeaders => None,
+        }
+    }
+}
+
+impl From<httparse::Error> for HttpError {
+    fn from(err: httparse::Error) -> HttpError {
+        match err {
+            httparse::Error::TooManyHeaders => HttpError::TooManyHeaders,
+            err => HttpError::Parse(err),
+        }
+    }
+}
+
+impl From<http::Error> for HttpError {
+    fn from(err: http::Error) -> HttpError {
+        HttpError::Http(err)
+    }
+}
+
+/// Parses an encapsulated HTTP request header section, such as `req-hdr`,
+/// into `http::request::Parts`.
+///
+/// Requires the `http` feature.
+///
+/// # Example
+///
+/// ```
+/// extern crate http;
+/// extern crate icaparse;
+///
+/// # fn main() {
+/// let parts = icaparse::request_parts(b"GET /index.html HTTP/1.1\r\nHost: example.org\r\n\r\n").unwrap();
+/// assert_eq!(parts.method, http::Method::GET);
+/// assert_eq!(parts.uri, "/index.html");
+/// assert_eq!(parts.headers["host"], "example.org");
+/// # }
+/// ```
+pub fn request_parts(section: &[u8]) -> Result<request::Parts, HttpError> {
+    let mut headers = [httparse::EMPTY_HEADER; MAX_HTTP_HEADERS];
+    let mut req = httparse::Request::new(&mut headers);
+    if req.parse(section)?.is_partial() {
+        return Err(HttpError::Incomplete);
+    }
+    let mut builder = http::Request::builder()
+        .method(Method::from_bytes(req.method.unwrap_or("").as_bytes()).map_err(http::Error::from)?)
+        .uri(req.path.unwrap_or(""))
+        .version(version(req.version));
+    for h in req.headers.iter() {
+        builder = builder.header(h.name, h.value);
+    }
+    Ok(builder.body(())?.into_parts().0)
+}
+
+/// Parses an encapsulated HTTP response header section, such as `res-hdr`,
+/// into `http::response::Parts`.
+///
+/// The reason-phrase is not kept. Requires the `http` feature.
+///
+/// # Example
+///
+/// ```
+/// extern crate http;
+/// extern crate icaparse;
+///
+/// # fn main() {
+/// let parts = icaparse::response_parts(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n").unwrap();
+/// assert_eq!(parts.status, http::StatusCode::NOT_FOUND);
+/// assert_eq!(parts.headers["content-length"], "0");
+/// # }
+/// ```
+pub fn response_parts(section: &[u8]) -> Result<response::Parts, HttpError> {
+    let mut headers = [httparse::EMPTY_HEADER; MAX_HTTP_HEADERS];
+    let mut res = httparse::Response::new(&mut headers);
+    if res.parse(section)?.is_partial() {
+        return Err(HttpError::Incomplete);
+    }
+    let mut builder = http::Response::builder()
+        .status(StatusCode::from_u16(res.code.unwrap_or(0)).map_err(http::Error::from)?)
+        .version(version(res.version));
+    for h in res.headers.iter() {
+        builder = builder.header(h.name, h.value);
+    }
+    Ok(builder.body(())?.into_parts().0)
+}
+
+/// Writes `http::request::Parts` as an HTTP request header section, ready to
+/// be encapsulated with `RequestBuilder::req_hdr` or `ResponseBuilder::req_hdr`.
+///
+/// Versions other than HTTP/1.0 are written as HTTP/1.1. Requires the `http`
+/// feature.
+///
+/// # Example
+///
+/// ```
+/// extern crate http;
+/// extern crate icaparse;
+///
+/// # fn main() {
+/// let (parts, ()) = http::Request::get("/").header("Host", "example.org").body(()).unwrap().into_parts();
+/// let req_hdr = icaparse::encode_request_parts(&parts);
+/// assert_eq!(req_hdr, b"GET / HTTP/1.1\r\nhost: example.org\r\n\r\n");
+///
+/// let req = icaparse::RequestBuilder::new("REQMOD", "icap://icap.example.org/filter")
+///     .req_hdr(&req_hdr);
+/// # }
+/// ```
+pub fn encode_request_parts(parts: &request::Parts) -> Vec<u8> {
+    let mut buf = Vec::new();
+    buf.extend_from_slice(parts.method.as_str().as_bytes());
+    buf.push(b' ');
+    buf.extend_from_slice(parts.uri.to_string().as_bytes());
+    buf.push(b' ');
+    buf.extend_from_slice(version_str(parts.version));
+    buf.extend_from_slice(b"\r\n");
+    put_headers(&mut buf, &parts.headers);
+    buf
+}
+
+/// Writes `http::response::Parts` as an HTTP response header section, ready
+/// to be encapsulated with `RequestBuilder::res_hdr` or
+/// `ResponseBuilder::res_hdr`.
+///
+/// The canonical reason-phrase of the status is used. Versions other than
+/// HTTP/1.0 are written as HTTP/1.1. Requires the `http` feature.
+pub fn encode_response_parts(parts: &response::Parts) -> Vec<u8> {
+    let mut buf = Vec::new();
+    buf.extend_from_slice(version_str(parts.version));
+    buf.push(b' ');
+    buf.extend_from_slice(parts.status.as_str().as_bytes());
+    buf.push(b' ');
+    buf.extend_from_slice(parts.status.canonical_reason().unwrap_or("").as_bytes());
+    buf.extend_from_slice(b"\r\n");
+    put_headers(&mut buf, &parts.headers);
+    buf
+}
+
+impl<'a> Sections<'a> {
+    /// Parses the `req-hdr` section, if present, into `http::request::Parts`.
+    ///
+    /// See `request_parts`. Requires the `http` feature.
+    pub fn request_parts(&self) -> Option<Result<request::Parts, HttpError>> {
+        self.get(SectionType::RequestHeader).map(request_parts)
+    }
+
+    /// Parses the `res-hdr` section, if present, into `http::response::Parts`.
+    ///
+    /// See `response_parts`. Requires the `http` feature.
+    pub fn response_parts(&self) -> Option<Result<response::Parts, HttpError>> {
+        self.get(SectionType::ResponseHeader).map(response_parts)
+    }
+}
+
+#[inline]
+fn version(version: Option<u8>) -> Version {
+    match version {
+        Some(0) => Version::HTTP_10,
+        _ => Version::HTTP_11,
+    }
+}
+
+#[inline]
+fn version_str(version: Version) -> &'static [u8] {
+    if version == Version::HTTP_10 {
+        b"HTTP/1.0"
+    } else {
+        b"HTTP/1.1"
+    }
+}
+
+fn put_headers(buf: &mut Vec<u8>, headers: &header::HeaderMap) {
+    for (name, value) in headers {
+        buf.extend_from_slice(name.as_str().as_bytes());
+        buf.extend_from_slice(b": ");
+        buf.extend_from_slice(value.as_bytes());
+        buf.extend_from_slice(b"\r\n");
+    }
+    buf.extend_from_slice(b"\r\n");
+}
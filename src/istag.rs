@@ -0,0 +1,79 @@
+use core::{fmt, result, str};
+
+use {trim_ows, Error};
+
+/// The longest tag allowed, not counting the quotes.
+const MAX_ISTAG_LEN: usize = 32;
+
+/// The value of an `ISTag` header, identifying the state of an ICAP service.
+///
+/// From [RFC 3507](https://tools.ietf.org/html/rfc3507#section-4.7), the tag
+/// is a quoted-string of at most 32 bytes, not counting the quotes. It changes
+/// whenever the service changes in a way that invalidates cached adaptations,
+/// so caches compare the tags of OPTIONS and RESPMOD replies for equality.
+///
+/// # Example
+///
+/// ```
+/// use icaparse::ISTag;
+///
+/// let options = ISTag::parse(b"\"W3E4R7U9-L2E4-2\"").unwrap();
+/// let respmod = ISTag::parse(b"\"W3E4R7U9-L2E4-2\"").unwrap();
+/// assert_eq!(options, respmod);
+/// assert_eq!(options.as_str(), "W3E4R7U9-L2E4-2");
+///
+/// assert!(ISTag::parse(b"W3E4R7U9-L2E4-2").is_err());
+/// ```
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct ISTag<'a> {
+    value: &'a str,
+}
+
+impl<'a> ISTag<'a> {
+    /// Parse the value of an `ISTag` header.
+    ///
+    /// Returns `Error::HeaderValue` if the value is not a quoted-string, or if
+    /// the tag is longer than 32 bytes.
+    pub fn parse(value: &'a [u8]) -> result::Result<ISTag<'a>, Error> {
+        let value = trim_ows(value);
+        if value.len() < 2 || value[0] != b'"' || value[value.len() - 1] != b'"' {
+            return Err(Error::HeaderValue);
+        }
+        let inner = &value[1..value.len() - 1];
+        if inner.len() > MAX_ISTAG_LEN {
+            return Err(Error::HeaderValue);
+        }
+        let mut escaped = false;
+        for &b in inner {
+            if escaped {
+                escaped = false;
+            } else if b == b'\\' {
+                escaped = true;
+            } else if b == b'"' {
+                return Err(Error::HeaderValue);
+            }
+            if b != b'\t' && (b < 0x20 || b == 0x7F) {
+                return Err(Error::HeaderValue);
+            }
+        }
+        if escaped {
+            return Err(Error::HeaderValue);
+        }
+        match str::from_utf8(inner) {
+            Ok(value) => Ok(ISTag { value }),
+            Err(_) => Err(Error::HeaderValue),
+        }
+    }
+
+    /// The tag, without the surrounding quotes.
+    #[inline]
+    pub fn as_str(&self) -> &'a str {
+        self.value
+    }
+}
+
+impl<'a> fmt::Display for ISTag<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "\"{}\"", self.value)
+    }
+}
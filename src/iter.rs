@@ -9,7 +9,7 @@ impl<'a> Bytes<'a> {
     #[inline]
     pub fn new(slice: &'a [u8]) -> Bytes<'a> {
         Bytes {
-            slice: slice,
+            slice,
             pos: 0
         }
     }
@@ -46,7 +46,7 @@ impl<'a> Bytes<'a> {
         unsafe {
             let ptr = self.slice.as_ptr();
             let head = slice::from_raw_parts(ptr, head_pos);
-            let tail = slice::from_raw_parts(ptr.offset(self.pos as isize), self.slice.len() - self.pos);
+            let tail = slice::from_raw_parts(ptr.add(self.pos), self.slice.len() - self.pos);
             self.pos = 0;
             self.slice = tail;
             head
@@ -113,7 +113,7 @@ impl<'a, 'b: 'a> Bytes8<'a, 'b> {
     #[inline]
     fn new(bytes: &'a mut Bytes<'b>) -> Bytes8<'a, 'b> {
         Bytes8 {
-            bytes: bytes,
+            bytes,
         }
     }
 
@@ -121,7 +121,7 @@ impl<'a, 'b: 'a> Bytes8<'a, 'b> {
     #[inline]
     fn new(bytes: &'a mut Bytes<'b>) -> Bytes8<'a, 'b> {
         Bytes8 {
-            bytes: bytes,
+            bytes,
             pos: 0,
         }
     }
@@ -11,16 +11,57 @@
 //! skipping bounds checks.
 //!
 //! This parser is based heavily on the httparse HTTP parsing library.
+//!
+//! # Features
+//!
+//! Without default features, the crate is `no_std` and never allocates.
+//!
+//! - `std` (default): implements `std::error::Error`, and writing messages to
+//!   an `io::Write`. Enables `alloc`.
+//! - `alloc`: owned copies of the encapsulated sections, with
+//!   `Sections::to_map`.
+//! - `codec`: `tokio-util` codecs framing ICAP messages, `ServerCodec` and
+//!   `ClientCodec`. Enables `std`.
+//! - `http`: conversions between the encapsulated HTTP headers and `http`
+//!   types, such as `request_parts`. Enables `std`.
 #[cfg(feature = "std")] extern crate std as core;
+#[cfg(feature = "alloc")] extern crate alloc;
+#[cfg(feature = "codec")] extern crate bytes;
+#[cfg(feature = "codec")] extern crate tokio_util;
+#[cfg(feature = "http")] extern crate http;
+extern crate httparse;
 
 use core::{fmt, result, str, slice};
+#[cfg(feature = "std")]
 use std::collections::HashMap;
+#[cfg(all(feature = "alloc", not(feature = "std")))]
+use alloc::collections::BTreeMap;
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
 
 use iter::Bytes;
 
+mod builder;
 mod iter;
+#[cfg(test)]
 mod test;
 
+pub use builder::{EncodeError, RequestBuilder, ResponseBuilder};
+#[cfg(feature = "codec")]
+pub use codec::{ClientCodec, CodecError, Frame, Limits, OwnedHeader, RequestHead, ResponseHead,
+                ServerCodec};
+pub use chunked::{Chunk, ChunkedDecoder, ChunkExtension, ChunkExtensions, ChunkExtensionsIter};
+pub use connection::{Connection, Event};
+#[cfg(feature = "http")]
+pub use http_parts::{encode_request_parts, encode_response_parts, request_parts, response_parts,
+                     HttpError};
+pub use error::ParseError;
+pub use istag::ISTag;
+pub use method::IcapMethod;
+pub use options::{OptionsResponse, TokenList, TokenListIter, Transfer};
+pub use status::IcapStatus;
+pub use uri::{IcapUri, QueryParams, Scheme, DEFAULT_PORT};
+
 macro_rules! next {
     ($bytes:ident) => ({
         match $bytes.next() {
@@ -44,13 +85,26 @@ macro_rules! expect {
 
 macro_rules! complete {
     ($e:expr) => {
-        match try!($e) {
+        match $e? {
             Status::Complete(v) => v,
             Status::Partial => return Ok(Status::Partial)
         }
     }
 }
 
+mod chunked;
+#[cfg(feature = "codec")]
+mod codec;
+mod connection;
+mod error;
+#[cfg(feature = "http")]
+mod http_parts;
+mod istag;
+mod method;
+mod options;
+mod status;
+mod uri;
+
 #[inline]
 fn shrink<T>(slice: &mut &mut [T], len: usize) {
     debug_assert!(slice.len() >= len);
@@ -168,7 +222,30 @@ pub enum Error {
     /// Invalid byte in HTTP version.
     Version,
     /// Missing encapsulated header
-    MissingEncapsulated
+    MissingEncapsulated,
+    /// Invalid Encapsulated header value.
+    Encapsulated,
+    /// Encapsulated section not allowed for the request method.
+    SectionNotAllowed,
+    /// Invalid chunk in an encapsulated body.
+    Chunk,
+    /// Encapsulated section offset does not match the encapsulated data.
+    SectionOffset,
+    /// Invalid encapsulated HTTP header section.
+    HttpHead,
+    /// A line of the head is longer than `ParserConfig::max_line_len`, or a
+    /// line of a chunked body is longer than
+    /// `ParserConfig::max_chunk_line_len`.
+    LineTooLong,
+    /// The headers are longer than `ParserConfig::max_headers_len`.
+    HeadersTooLarge,
+    /// An Encapsulated offset is larger than
+    /// `ParserConfig::max_encapsulated_offset`.
+    EncapsulatedOffsetTooLarge,
+    /// A chunk is larger than `ParserConfig::max_chunk_size`.
+    ChunkTooLarge,
+    /// Invalid ICAP URI.
+    Uri,
 }
 
 impl Error {
@@ -183,6 +260,16 @@ impl Error {
             Error::TooManyHeaders => "too many headers",
             Error::Version => "invalid ICAP version",
             Error::MissingEncapsulated => "missing encapsulated ICAP header",
+            Error::Encapsulated => "invalid Encapsulated header",
+            Error::SectionNotAllowed => "encapsulated section not allowed for ICAP method",
+            Error::Chunk => "invalid chunk in encapsulated body",
+            Error::SectionOffset => "invalid encapsulated section offset",
+            Error::HttpHead => "invalid encapsulated HTTP header",
+            Error::LineTooLong => "line too long",
+            Error::HeadersTooLarge => "headers too large",
+            Error::EncapsulatedOffsetTooLarge => "encapsulated offset too large",
+            Error::ChunkTooLarge => "chunk too large",
+            Error::Uri => "invalid ICAP URI",
         }
     }
 }
@@ -218,6 +305,9 @@ impl fmt::Display for InvalidChunkSize {
 /// a `Ok(Status::Partial)`.
 pub type Result<T> = result::Result<Status<T>, Error>;
 
+/// The result of parsing chunked data.
+type ChunkResult<T> = result::Result<Status<T>, InvalidChunkSize>;
+
 /// The result of a successful parse pass.
 ///
 /// `Complete` is used when the buffer contained the complete value.
@@ -296,8 +386,10 @@ pub struct Request<'headers, 'buf: 'headers> {
     pub version: Option<u8>,
     /// The request headers.
     pub headers: &'headers mut [Header<'buf>],
-    /// The sections of the encapsulated body listed in the Encapsulated header
-    pub encapsulated_sections: Option<HashMap<SectionType, Vec<u8>>>
+    /// The sections of the encapsulated body listed in the Encapsulated header,
+    /// borrowed from the parsed buffer.
+    pub sections: Option<Sections<'buf>>,
+    encapsulated_offset: Option<usize>,
 }
 
 impl<'h, 'b> Request<'h, 'b> {
@@ -308,36 +400,627 @@ impl<'h, 'b> Request<'h, 'b> {
             method: None,
             path: None,
             version: None,
-            headers: headers,
-            encapsulated_sections: None,
+            headers,
+            sections: None,
+            encapsulated_offset: None,
         }
     }
 
     /// Try to parse a buffer of bytes into the Request.
+    ///
+    /// On success, returns the number of bytes that belong to this request:
+    /// the ICAP head, followed by the encapsulated sections up to the end of
+    /// the body. Any bytes after that belong to the next message. If the body
+    /// is not complete yet, every byte after the head is counted as part of
+    /// it; see `Sections::is_body_complete`.
+    ///
+    /// This uses the default `ParserConfig`, so the Encapsulated sections
+    /// must be allowed for the request method, and the head is checked in
+    /// `ParseMode::Standard`.
     pub fn parse(&mut self, buf: &'b [u8]) -> Result<usize> {
+        self.parse_with_config(buf, &ParserConfig::default())
+    }
+
+    /// Like `parse`, but also parses the encapsulated HTTP headers, in the
+    /// same pass and without copying.
+    ///
+    /// The `req-hdr` section is parsed into `http_req`, and the `res-hdr`
+    /// section into `http_res`, when both the section and the destination are
+    /// present. Either destination may be `None` to skip that section.
+    /// Returns `Error::HttpHead` if a section is not a valid HTTP head, and
+    /// `Error::TooManyHeaders` if its destination has too few headers.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// extern crate httparse;
+    /// extern crate icaparse;
+    ///
+    /// # fn main() {
+    /// let buf = b"REQMOD icap://icap.example.org/filter ICAP/1.0\r\n\
+    /// Encapsulated: req-hdr=0, null-body=37\r\n\r\n\
+    /// GET / HTTP/1.1\r\nHost: example.org\r\n\r\n";
+    /// let mut headers = [icaparse::EMPTY_HEADER; 4];
+    /// let mut http_headers = [httparse::EMPTY_HEADER; 4];
+    /// let mut req = icaparse::Request::new(&mut headers);
+    /// let mut http_req = httparse::Request::new(&mut http_headers);
+    /// assert!(req.parse_with_http(buf, Some(&mut http_req), None).unwrap().is_complete());
+    /// assert_eq!(http_req.method, Some("GET"));
+    /// assert_eq!(http_req.headers[0].value, b"example.org");
+    /// # }
+    /// ```
+    pub fn parse_with_http(&mut self, buf: &'b [u8],
+                           http_req: Option<&mut httparse::Request<'_, 'b>>,
+                           http_res: Option<&mut httparse::Response<'_, 'b>>) -> Result<usize> {
+        self.parse_with_http_config(buf, &ParserConfig::default(), http_req, http_res)
+    }
+
+    fn parse_with_http_config(&mut self, buf: &'b [u8], config: &ParserConfig,
+                              http_req: Option<&mut httparse::Request<'_, 'b>>,
+                              http_res: Option<&mut httparse::Response<'_, 'b>>) -> Result<usize> {
+        let len = complete!(self.parse_with_config(buf, config));
+        parse_http_heads(self.sections.as_ref(), http_req, http_res)?;
+        Ok(Status::Complete(len))
+    }
+
+    /// Like `parse`, but on failure finds where in the buffer the error is.
+    ///
+    /// See `ParseError` for an example.
+    pub fn parse_detailed(&mut self, buf: &'b [u8]) -> result::Result<Status<usize>, ParseError<'b>> {
+        self.parse_detailed_with_config(buf, &ParserConfig::default())
+    }
+
+    fn parse_detailed_with_config(&mut self, buf: &'b [u8], config: &ParserConfig)
+            -> result::Result<Status<usize>, ParseError<'b>> {
+        match self.parse_with_config(buf, config) {
+            Err(kind) => {
+                let lenient = config.mode == ParseMode::Lenient;
+                let err = ParseError::locate(kind, buf, lenient, |prefix| self.parse_with_config(prefix, config).is_err());
+                let _ = self.parse_with_config(buf, config);
+                Err(err.with_headers(self.headers))
+            },
+            Ok(status) => Ok(status),
+        }
+    }
+
+    fn parse_with_config(&mut self, buf: &'b [u8], config: &ParserConfig) -> Result<usize> {
+        self.encapsulated_offset = None;
+        self.sections = None;
+        let result = self.parse_message(buf, config);
+        if result.is_ok() {
+            config.check_head(&buf[..self.encapsulated_offset.unwrap_or(buf.len())])?;
+        }
+        result
+    }
+
+    fn parse_message(&mut self, buf: &'b [u8], config: &ParserConfig) -> Result<usize> {
         let orig_len = buf.len();
         let mut bytes = Bytes::new(buf);
         complete!(skip_empty_lines(&mut bytes));
-        self.method = Some(complete!(parse_token(&mut bytes)));
+        let method = IcapMethod::from(complete!(parse_token(&mut bytes)));
+        self.method = Some(method.as_str());
         self.path = Some(complete!(parse_token(&mut bytes)));
         self.version = Some(complete!(parse_version(&mut bytes)));
         newline!(bytes);
 
         let len = orig_len - bytes.len();
-        let headers_len = complete!(parse_headers_iter(&mut self.headers, &mut bytes));
-        match self.headers.iter().find(|&h| h.name == "Encapsulated") {
+        let headers_len = complete!(parse_headers_iter(&mut self.headers, &mut bytes, config.mode));
+        let head_len = len + headers_len;
+        self.encapsulated_offset = Some(head_len);
+        let preview = find_header(self.headers, "Preview").is_some();
+        match find_header(self.headers, "Encapsulated") {
             Some(h) => {
-                self.encapsulated_sections = Some(parse_encapsulated(h.value, &buf[len+headers_len..buf.len()]));
-                Ok(Status::Complete(orig_len + len - len + headers_len - headers_len))
+                let (sections, encapsulated_len) = complete!(parse_encapsulated(h.value, &buf[head_len..], config, preview));
+                if !config.allows_any_sections() {
+                    check_sections(method, &sections)?;
+                }
+                self.sections = Some(sections);
+                Ok(Status::Complete(head_len + encapsulated_len))
             },
             None => {
-                match self.method {
-                  Some("OPTIONS") => Ok(Status::Complete(orig_len)),
-                  _ =>  Err(Error::MissingEncapsulated)
+                if method.requires_encapsulated() {
+                    Err(Error::MissingEncapsulated)
+                } else {
+                    Ok(Status::Complete(head_len))
                 }
             }
         }
+    }
+
+    /// The request method as an `IcapMethod`, once it has been parsed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use icaparse::IcapMethod;
+    ///
+    /// let buf = b"OPTIONS icap://icap.example.org/filter ICAP/1.0\r\n\r\n";
+    /// let mut headers = [icaparse::EMPTY_HEADER; 4];
+    /// let mut req = icaparse::Request::new(&mut headers);
+    /// req.parse(buf).unwrap();
+    /// assert_eq!(req.icap_method(), Some(IcapMethod::Options));
+    /// ```
+    #[inline]
+    pub fn icap_method(&self) -> Option<IcapMethod<'b>> {
+        self.method.map(IcapMethod::from)
+    }
+
+    /// The offset in the parsed buffer at which the encapsulated data starts,
+    /// right after the ICAP head.
+    ///
+    /// This is `None` until the head has been completely parsed.
+    #[inline]
+    pub fn encapsulated_offset(&self) -> Option<usize> {
+        self.encapsulated_offset
+    }
+
+    /// Parse the request path into its parts, once it has been parsed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let buf = b"OPTIONS icap://icap.example.org:1345/filter ICAP/1.0\r\n\r\n";
+    /// let mut headers = [icaparse::EMPTY_HEADER; 4];
+    /// let mut req = icaparse::Request::new(&mut headers);
+    /// req.parse(buf).unwrap();
+    /// let uri = req.uri().unwrap().unwrap();
+    /// assert_eq!(uri.port(), 1345);
+    /// assert_eq!(uri.service(), "filter");
+    /// ```
+    #[inline]
+    pub fn uri(&self) -> result::Result<Option<IcapUri<'b>>, Error> {
+        match self.path {
+            Some(path) => IcapUri::parse(path).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Parse the value of the `Preview` header, if present.
+    ///
+    /// A request with a `Preview` header carries only the first bytes of the
+    /// encapsulated body. Parsing stops at the end of the preview, and
+    /// `Request::preview_ieof` tells whether it held the whole body or more
+    /// follows after the server replies `ICAP/1.0 100 Continue`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let buf = b"RESPMOD / ICAP/1.0\r\nPreview: 1024\r\nEncapsulated: null-body=0\r\n\r\n";
+    /// let mut headers = [icaparse::EMPTY_HEADER; 4];
+    /// let mut req = icaparse::Request::new(&mut headers);
+    /// req.parse(buf).unwrap();
+    /// assert_eq!(req.preview(), Ok(Some(1024)));
+    /// ```
+    pub fn preview(&self) -> result::Result<Option<usize>, Error> {
+        match find_header(self.headers, "Preview") {
+            Some(h) => parse_decimal(h.value).map(Some).ok_or(Error::HeaderValue),
+            None => Ok(None),
+        }
+    }
+
+    /// Whether the preview ended with the `ieof` extension, once its last
+    /// chunk has been parsed.
+    ///
+    /// Returns `Some(true)` if the preview held the whole body, and
+    /// `Some(false)` if the rest of the body follows once the server has
+    /// replied `ICAP/1.0 100 Continue`. Returns `None` if the request has no
+    /// `Preview` header, or the end of the preview has not been parsed yet.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let buf = b"RESPMOD / ICAP/1.0\r\nPreview: 4\r\nEncapsulated: res-body=0\r\n\r\n4\r\nRust\r\n0\r\n\r\n";
+    /// let mut headers = [icaparse::EMPTY_HEADER; 4];
+    /// let mut req = icaparse::Request::new(&mut headers);
+    /// req.parse(buf).unwrap();
+    /// assert_eq!(req.preview_ieof(), Some(false));
+    /// assert!(!req.sections.unwrap().is_body_complete());
+    /// ```
+    pub fn preview_ieof(&self) -> Option<bool> {
+        find_header(self.headers, "Preview")?;
+        match self.sections {
+            Some(ref sections) if sections.is_preview_end() => Some(false),
+            Some(ref sections) if sections.is_body_complete() => Some(true),
+            _ => None,
+        }
+    }
+
+    /// Copies the encapsulated sections into an owned map.
+    ///
+    /// This was a field filled on every parse; the sections are now borrowed
+    /// from the buffer as `sections`, and only copied when asked for.
+    ///
+    /// A `BTreeMap` is returned instead without the `std` feature.
+    #[cfg(feature = "alloc")]
+    pub fn encapsulated_sections(&self) -> Option<SectionMap> {
+        self.sections.as_ref().map(Sections::to_map)
+    }
+
+    /// Parse the value of the `Allow` header, which is empty if absent.
+    ///
+    /// A client sends `Allow: 204` when the server may reply
+    /// `204 No Content` outside of a preview, and `Allow: 206` when it may
+    /// reply `206 Partial Content`. See `Request::may_reply` to check a reply.
+    #[inline]
+    pub fn allow(&self) -> result::Result<TokenList<'b>, Error> {
+        options::list(self.headers, "Allow")
+    }
+
+    /// Decides whether the server may reply to this request with the given
+    /// status.
+    ///
+    /// `during_preview` is true if the server replies after reading a preview,
+    /// instead of sending `ICAP/1.0 100 Continue`.
+    ///
+    /// From [RFC 3507](https://tools.ietf.org/html/rfc3507#section-4.6), a
+    /// `204 No Content` reply is only permitted if the request carries
+    /// `Allow: 204`, or during a preview. Likewise, `206 Partial Content` is
+    /// only permitted if the request carries `Allow: 206`. Any other status is
+    /// always permitted.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use icaparse::IcapStatus;
+    ///
+    /// let buf = b"RESPMOD / ICAP/1.0\r\nPreview: 0\r\nEncapsulated: res-body=0\r\n\r\n0\r\n\r\n";
+    /// let mut headers = [icaparse::EMPTY_HEADER; 4];
+    /// let mut req = icaparse::Request::new(&mut headers);
+    /// req.parse(buf).unwrap();
+    /// assert_eq!(req.may_reply(IcapStatus::NoContent, true), Ok(true));
+    /// assert_eq!(req.may_reply(IcapStatus::NoContent, false), Ok(false));
+    /// ```
+    pub fn may_reply(&self, status: IcapStatus, during_preview: bool) -> result::Result<bool, Error> {
+        match status {
+            IcapStatus::NoContent => {
+                let preview = during_preview && self.preview()?.is_some();
+                Ok(preview || options::allows(self.allow()?, 204))
+            },
+            IcapStatus::PartialContent => Ok(options::allows(self.allow()?, 206)),
+            _ => Ok(true),
+        }
+    }
+}
+
+#[inline]
+fn find_header<'h, 'b>(headers: &'h [Header<'b>], name: &str) -> Option<&'h Header<'b>> {
+    headers.iter().find(|h| h.name.eq_ignore_ascii_case(name))
+}
+
+/// Parses a header value made only of decimal digits, ignoring surrounding
+/// whitespace.
+#[inline]
+fn parse_decimal(value: &[u8]) -> Option<usize> {
+    let value = trim_ows(value);
+    if value.is_empty() {
+        return None;
+    }
+    let mut n: usize = 0;
+    for &b in value {
+        match b {
+            b'0'..=b'9' => {
+                n = n.checked_mul(10)?.checked_add(usize::from(b - b'0'))?;
+            },
+            _ => return None,
+        }
+    }
+    Some(n)
+}
+
+/// Parses the encapsulated HTTP header sections into the given destinations.
+fn parse_http_heads<'b>(sections: Option<&Sections<'b>>,
+                        http_req: Option<&mut httparse::Request<'_, 'b>>,
+                        http_res: Option<&mut httparse::Response<'_, 'b>>) -> result::Result<(), Error> {
+    let sections = match sections {
+        Some(sections) => sections,
+        None => return Ok(()),
+    };
+    if let (Some(http_req), Some(section)) = (http_req, sections.get(SectionType::RequestHeader)) {
+        check_http_head(http_req.parse(section))?;
+    }
+    if let (Some(http_res), Some(section)) = (http_res, sections.get(SectionType::ResponseHeader)) {
+        check_http_head(http_res.parse(section))?;
+    }
+    Ok(())
+}
+
+#[inline]
+fn check_http_head(result: httparse::Result<usize>) -> result::Result<(), Error> {
+    match result {
+        Ok(httparse::Status::Complete(_)) => Ok(()),
+        Err(httparse::Error::TooManyHeaders) => Err(Error::TooManyHeaders),
+        _ => Err(Error::HttpHead),
+    }
+}
+
+/// Checks the Encapsulated sections of a request are allowed for its method.
+fn check_sections(method: IcapMethod, sections: &Sections) -> result::Result<(), Error> {
+    if sections.iter().all(|s| method.allows_section(s.name)) {
+        Ok(())
+    } else {
+        Err(Error::SectionNotAllowed)
+    }
+}
+
+/// How strictly the syntax of an ICAP head is checked.
+///
+/// Header names, such as `Encapsulated`, are matched case-insensitively in
+/// every mode.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ParseMode {
+    /// Only the syntax of RFC 3507: every line must end with CRLF, and a
+    /// response status line must have a reason-phrase.
+    Strict,
+    /// The default. A bare LF is accepted as a line ending, as RFC 7230
+    /// allows, and a response may omit its reason-phrase.
+    #[default]
+    Standard,
+    /// Like `Standard`, but also accepts whitespace between a header name
+    /// and its colon, and obsolete line folding, as sent by some ICAP
+    /// clients.
+    ///
+    /// A folded header value keeps the line breaks within it, such as
+    /// `b"a,\r\n b"`. The typed accessors, such as `Request::preview`,
+    /// treat them as whitespace.
+    ///
+    /// A request may also list any combination of Encapsulated sections, as
+    /// with `ParserConfig::allow_any_sections`.
+    Lenient,
+}
+
+/// Configuration for parsing requests and responses.
+///
+/// Besides the checks of the Encapsulated sections and the `ParseMode`, the
+/// configuration sets limits on the size of a message. As a message is
+/// parsed again from the start each time more bytes arrive, the limits are
+/// also checked while it is incomplete, so that hostile input cannot make the
+/// caller buffer without bound. No limits are set by default.
+///
+/// # Example
+///
+/// ```
+/// let buf = b"REQMOD / ICAP/1.0\r\nEncapsulated: res-hdr=0, res-body=19\r\n\r\nHTTP/1.1 200 OK\r\n\r\n0\r\n\r\n";
+/// let mut headers = [icaparse::EMPTY_HEADER; 4];
+///
+/// let mut req = icaparse::Request::new(&mut headers);
+/// assert_eq!(req.parse(buf), Err(icaparse::Error::SectionNotAllowed));
+///
+/// let mut req = icaparse::Request::new(&mut headers);
+/// let mut config = icaparse::ParserConfig::default();
+/// config.allow_any_sections(true);
+/// assert!(config.parse_request(&mut req, buf).unwrap().is_complete());
+///
+/// let mut req = icaparse::Request::new(&mut headers);
+/// config.max_line_len(Some(16));
+/// assert_eq!(config.parse_request(&mut req, buf), Err(icaparse::Error::LineTooLong));
+/// ```
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ParserConfig {
+    allow_any_sections: bool,
+    mode: ParseMode,
+    max_line_len: Option<usize>,
+    max_headers_len: Option<usize>,
+    max_encapsulated_offset: Option<usize>,
+    max_chunk_size: Option<u64>,
+    max_chunk_line_len: Option<usize>,
+    skip_body: bool,
+}
+
+impl ParserConfig {
+    /// Sets whether a request may list any combination of Encapsulated
+    /// sections, regardless of its method.
+    ///
+    /// By default, the sections are checked against the layouts RFC 3507
+    /// allows for `REQMOD`, `RESPMOD`, and `OPTIONS` requests. Any
+    /// combination is always allowed in `ParseMode::Lenient`.
+    pub fn allow_any_sections(&mut self, value: bool) -> &mut ParserConfig {
+        self.allow_any_sections = value;
+        self
+    }
+
+    /// Whether the Encapsulated sections of a request are left unchecked.
+    #[inline]
+    fn allows_any_sections(&self) -> bool {
+        self.allow_any_sections || self.mode == ParseMode::Lenient
+    }
+
+    /// Sets how strictly the syntax of the ICAP head is checked.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use icaparse::{ParseMode, ParserConfig};
+    ///
+    /// let buf = b"OPTIONS icap://icap.example.org/filter ICAP/1.0\nHost : icap.example.org\n\n";
+    /// let mut headers = [icaparse::EMPTY_HEADER; 4];
+    ///
+    /// let mut req = icaparse::Request::new(&mut headers);
+    /// assert_eq!(req.parse(buf), Err(icaparse::Error::HeaderName));
+    ///
+    /// let mut req = icaparse::Request::new(&mut headers);
+    /// let mut config = ParserConfig::default();
+    /// config.mode(ParseMode::Lenient);
+    /// assert!(config.parse_request(&mut req, buf).unwrap().is_complete());
+    /// assert_eq!(req.headers[0].name, "Host");
+    /// ```
+    pub fn mode(&mut self, value: ParseMode) -> &mut ParserConfig {
+        self.mode = value;
+        self
+    }
+
+    /// Sets the longest line allowed in the ICAP head, not counting the line
+    /// break, or `None` for no limit.
+    ///
+    /// Longer lines are rejected with `Error::LineTooLong`.
+    pub fn max_line_len(&mut self, value: Option<usize>) -> &mut ParserConfig {
+        self.max_line_len = value;
+        self
+    }
+
+    /// Sets the most bytes allowed in the ICAP headers, from the end of the
+    /// start line to the end of the head, or `None` for no limit.
+    ///
+    /// Larger headers are rejected with `Error::HeadersTooLarge`.
+    pub fn max_headers_len(&mut self, value: Option<usize>) -> &mut ParserConfig {
+        self.max_headers_len = value;
+        self
+    }
+
+    /// Sets the largest offset allowed in the Encapsulated header, or `None`
+    /// for no limit.
+    ///
+    /// The encapsulated HTTP headers are buffered until the body offset is
+    /// reached, so this bounds their size. Larger offsets are rejected with
+    /// `Error::EncapsulatedOffsetTooLarge`.
+    pub fn max_encapsulated_offset(&mut self, value: Option<usize>) -> &mut ParserConfig {
+        self.max_encapsulated_offset = value;
+        self
+    }
+
+    /// Sets the largest chunk allowed in the encapsulated body, or `None` for
+    /// no limit.
+    ///
+    /// Larger chunks are rejected with `Error::ChunkTooLarge`.
+    pub fn max_chunk_size(&mut self, value: Option<u64>) -> &mut ParserConfig {
+        self.max_chunk_size = value;
+        self
+    }
+
+    /// Sets the longest chunk size line, including its extensions, or
+    /// trailer line allowed in the encapsulated body, or `None` for no limit.
+    ///
+    /// Longer lines are rejected with `Error::LineTooLong`. See
+    /// `ChunkedDecoder::with_config` to apply this when decoding a body.
+    pub fn max_chunk_line_len(&mut self, value: Option<usize>) -> &mut ParserConfig {
+        self.max_chunk_line_len = value;
+        self
+    }
+
+    /// Sets whether parsing stops at the start of the encapsulated body,
+    /// instead of scanning the chunks buffered so far.
+    ///
+    /// The body is scanned from its start on each parse, so a caller that
+    /// parses again as more bytes arrive would scan it over and over. When
+    /// skipped, the body section is empty, `Sections::is_body_complete` is
+    /// false, and the returned length ends where the body starts; decode the
+    /// body with a `ChunkedDecoder` instead, as `Connection` does.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let buf = b"REQMOD / ICAP/1.0\r\nEncapsulated: req-body=0\r\n\r\n4\r\nRust\r\n";
+    /// let mut headers = [icaparse::EMPTY_HEADER; 4];
+    /// let mut req = icaparse::Request::new(&mut headers);
+    /// let mut config = icaparse::ParserConfig::default();
+    /// config.skip_body(true);
+    /// assert_eq!(config.parse_request(&mut req, buf), Ok(icaparse::Status::Complete(47)));
+    /// assert_eq!(req.sections.unwrap().get(icaparse::SectionType::RequestBody), Some(&b""[..]));
+    /// ```
+    pub fn skip_body(&mut self, value: bool) -> &mut ParserConfig {
+        self.skip_body = value;
+        self
+    }
+
+    /// Parse a request with this configuration.
+    pub fn parse_request<'h, 'b>(&self, request: &mut Request<'h, 'b>, buf: &'b [u8]) -> Result<usize> {
+        request.parse_with_config(buf, self)
+    }
+
+    /// Parse a response with this configuration.
+    pub fn parse_response<'h, 'b>(&self, response: &mut Response<'h, 'b>, buf: &'b [u8]) -> Result<usize> {
+        response.parse_with_config(buf, self)
+    }
+
+    /// Parse a request with this configuration, as `Request::parse_with_http`
+    /// does.
+    pub fn parse_request_with_http<'h, 'b>(&self, request: &mut Request<'h, 'b>, buf: &'b [u8],
+                                           http_req: Option<&mut httparse::Request<'_, 'b>>,
+                                           http_res: Option<&mut httparse::Response<'_, 'b>>)
+            -> Result<usize> {
+        request.parse_with_http_config(buf, self, http_req, http_res)
+    }
+
+    /// Parse a response with this configuration, as
+    /// `Response::parse_with_http` does.
+    pub fn parse_response_with_http<'h, 'b>(&self, response: &mut Response<'h, 'b>, buf: &'b [u8],
+                                            http_req: Option<&mut httparse::Request<'_, 'b>>,
+                                            http_res: Option<&mut httparse::Response<'_, 'b>>)
+            -> Result<usize> {
+        response.parse_with_http_config(buf, self, http_req, http_res)
+    }
+
+    /// Parse a request with this configuration, as `Request::parse_detailed`
+    /// does.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let buf = b"OPTIONS / ICAP/1.0\r\nHost: icap.example.org\r\nX-Long: aaaaaaaaaaaaaaaa\r\n\r\n";
+    /// let mut headers = [icaparse::EMPTY_HEADER; 4];
+    /// let mut req = icaparse::Request::new(&mut headers);
+    /// let mut config = icaparse::ParserConfig::default();
+    /// config.max_line_len(Some(22));
+    /// let err = config.parse_request_detailed(&mut req, buf).unwrap_err();
+    /// assert_eq!(err.kind(), icaparse::Error::LineTooLong);
+    /// assert_eq!(err.offset(), 66);
+    /// assert_eq!(err.header_name(), Some(&b"X-Long"[..]));
+    /// ```
+    pub fn parse_request_detailed<'h, 'b>(&self, request: &mut Request<'h, 'b>, buf: &'b [u8])
+            -> result::Result<Status<usize>, ParseError<'b>> {
+        request.parse_detailed_with_config(buf, self)
+    }
+
+    /// Parse a response with this configuration, as
+    /// `Response::parse_detailed` does.
+    pub fn parse_response_detailed<'h, 'b>(&self, response: &mut Response<'h, 'b>, buf: &'b [u8])
+            -> result::Result<Status<usize>, ParseError<'b>> {
+        response.parse_detailed_with_config(buf, self)
+    }
+
+    /// Parse a chunk size, as `parse_chunk_size` does, with this
+    /// configuration.
+    ///
+    /// Returns `Error::Chunk` if the size line is invalid.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut config = icaparse::ParserConfig::default();
+    /// config.max_chunk_size(Some(0xFFFF)).max_chunk_line_len(Some(8));
+    /// assert_eq!(config.parse_chunk_size(b"FFFF\r\n"), Ok(icaparse::Status::Complete((6, 0xFFFF))));
+    /// assert_eq!(config.parse_chunk_size(b"10000\r\n"), Err(icaparse::Error::ChunkTooLarge));
+    /// assert_eq!(config.parse_chunk_size(b"1;ext=abc\r\n"), Err(icaparse::Error::LineTooLong));
+    /// ```
+    pub fn parse_chunk_size(&self, buf: &[u8]) -> result::Result<Status<(usize, u64)>, Error> {
+        let (len, size, _) = complete!(ChunkedDecoder::new().with_config(self).parse_size_line(buf));
+        Ok(Status::Complete((len, size)))
+    }
 
+    /// Checks the lines and headers of a head, or of the part of it received
+    /// so far.
+    fn check_head(&self, head: &[u8]) -> result::Result<(), Error> {
+        if self.mode == ParseMode::Strict &&
+                (head.first() == Some(&b'\n') || head.windows(2).any(|w| w[1] == b'\n' && w[0] != b'\r')) {
+            return Err(Error::NewLine);
+        }
+        if let Some(max) = self.max_line_len {
+            let too_long = head.split(|&b| b == b'\n').any(|line| {
+                let len = if line.ends_with(b"\r") { line.len() - 1 } else { line.len() };
+                len > max
+            });
+            if too_long {
+                return Err(Error::LineTooLong);
+            }
+        }
+        if let Some(max) = self.max_headers_len {
+            let start = head.iter().position(|&b| b != b'\r' && b != b'\n').unwrap_or(head.len());
+            let headers_start = head[start..].iter().position(|&b| b == b'\n')
+                .map_or(head.len(), |i| start + i + 1);
+            if head.len() - headers_start > max {
+                return Err(Error::HeadersTooLarge);
+            }
+        }
+        Ok(())
     }
 }
 
@@ -374,7 +1057,11 @@ pub struct Response<'headers, 'buf: 'headers> {
     /// The response reason-phrase, such as `OK`.
     pub reason: Option<&'buf str>,
     /// The response headers.
-    pub headers: &'headers mut [Header<'buf>]
+    pub headers: &'headers mut [Header<'buf>],
+    /// The sections of the encapsulated body listed in the Encapsulated header,
+    /// borrowed from the parsed buffer.
+    pub sections: Option<Sections<'buf>>,
+    encapsulated_offset: Option<usize>,
 }
 
 impl<'h, 'b> Response<'h, 'b> {
@@ -385,12 +1072,41 @@ impl<'h, 'b> Response<'h, 'b> {
             version: None,
             code: None,
             reason: None,
-            headers: headers,
+            headers,
+            sections: None,
+            encapsulated_offset: None,
         }
     }
 
+    /// Copies the encapsulated sections into an owned map.
+    ///
+    /// This was a field filled on every parse; the sections are now borrowed
+    /// from the buffer as `sections`, and only copied when asked for.
+    ///
+    /// A `BTreeMap` is returned instead without the `std` feature.
+    #[cfg(feature = "alloc")]
+    pub fn encapsulated_sections(&self) -> Option<SectionMap> {
+        self.sections.as_ref().map(Sections::to_map)
+    }
+
     /// Try to parse a buffer of bytes into this `Response`.
+    ///
+    /// See `Request::parse` for the number of bytes returned on success.
     pub fn parse(&mut self, buf: &'b [u8]) -> Result<usize> {
+        self.parse_with_config(buf, &ParserConfig::default())
+    }
+
+    fn parse_with_config(&mut self, buf: &'b [u8], config: &ParserConfig) -> Result<usize> {
+        self.encapsulated_offset = None;
+        self.sections = None;
+        let result = self.parse_message(buf, config);
+        if result.is_ok() {
+            config.check_head(&buf[..self.encapsulated_offset.unwrap_or(buf.len())])?;
+        }
+        result
+    }
+
+    fn parse_message(&mut self, buf: &'b [u8], config: &ParserConfig) -> Result<usize> {
         let orig_len = buf.len();
         let mut bytes = Bytes::new(buf);
 
@@ -413,18 +1129,142 @@ impl<'h, 'b> Response<'h, 'b> {
                 bytes.slice();
                 self.reason = Some(complete!(parse_reason(&mut bytes)));
             },
-            b'\r' => {
+            b'\r' if config.mode != ParseMode::Strict => {
                 expect!(bytes.next() == b'\n' => Err(Error::Status));
                 self.reason = Some("");
             },
-            b'\n' => self.reason = Some(""),
+            b'\n' if config.mode != ParseMode::Strict => self.reason = Some(""),
             _ => return Err(Error::Status),
         }
 
 
         let len = orig_len - bytes.len();
-        let headers_len = complete!(parse_headers_iter(&mut self.headers, &mut bytes));
-        Ok(Status::Complete(len + headers_len))
+        let headers_len = complete!(parse_headers_iter(&mut self.headers, &mut bytes, config.mode));
+        let head_len = len + headers_len;
+        self.encapsulated_offset = Some(head_len);
+        match find_header(self.headers, "Encapsulated") {
+            Some(h) => {
+                let (sections, encapsulated_len) = complete!(parse_encapsulated(h.value, &buf[head_len..], config, false));
+                self.sections = Some(sections);
+                Ok(Status::Complete(head_len + encapsulated_len))
+            },
+            None => Ok(Status::Complete(head_len))
+        }
+    }
+
+    /// Like `parse`, but also parses the encapsulated HTTP headers, in the
+    /// same pass and without copying.
+    ///
+    /// The `req-hdr` section is parsed into `http_req`, and the `res-hdr`
+    /// section into `http_res`, when both the section and the destination are
+    /// present. Either destination may be `None` to skip that section.
+    /// Returns `Error::HttpHead` if a section is not a valid HTTP head, and
+    /// `Error::TooManyHeaders` if its destination has too few headers.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// extern crate httparse;
+    /// extern crate icaparse;
+    ///
+    /// # fn main() {
+    /// let buf = b"ICAP/1.0 200 OK\r\n\
+    /// Encapsulated: res-hdr=0, res-body=19\r\n\r\n\
+    /// HTTP/1.1 200 OK\r\n\r\n\
+    /// 4\r\nRust\r\n0\r\n\r\n";
+    /// let mut headers = [icaparse::EMPTY_HEADER; 4];
+    /// let mut http_headers = [httparse::EMPTY_HEADER; 4];
+    /// let mut res = icaparse::Response::new(&mut headers);
+    /// let mut http_res = httparse::Response::new(&mut http_headers);
+    /// assert!(res.parse_with_http(buf, None, Some(&mut http_res)).unwrap().is_complete());
+    /// assert_eq!(http_res.code, Some(200));
+    /// # }
+    /// ```
+    pub fn parse_with_http(&mut self, buf: &'b [u8],
+                           http_req: Option<&mut httparse::Request<'_, 'b>>,
+                           http_res: Option<&mut httparse::Response<'_, 'b>>) -> Result<usize> {
+        self.parse_with_http_config(buf, &ParserConfig::default(), http_req, http_res)
+    }
+
+    fn parse_with_http_config(&mut self, buf: &'b [u8], config: &ParserConfig,
+                              http_req: Option<&mut httparse::Request<'_, 'b>>,
+                              http_res: Option<&mut httparse::Response<'_, 'b>>) -> Result<usize> {
+        let len = complete!(self.parse_with_config(buf, config));
+        parse_http_heads(self.sections.as_ref(), http_req, http_res)?;
+        Ok(Status::Complete(len))
+    }
+
+    /// Like `parse`, but on failure finds where in the buffer the error is.
+    ///
+    /// See `ParseError` for an example.
+    pub fn parse_detailed(&mut self, buf: &'b [u8]) -> result::Result<Status<usize>, ParseError<'b>> {
+        self.parse_detailed_with_config(buf, &ParserConfig::default())
+    }
+
+    fn parse_detailed_with_config(&mut self, buf: &'b [u8], config: &ParserConfig)
+            -> result::Result<Status<usize>, ParseError<'b>> {
+        match self.parse_with_config(buf, config) {
+            Err(kind) => {
+                let lenient = config.mode == ParseMode::Lenient;
+                let err = ParseError::locate(kind, buf, lenient, |prefix| self.parse_with_config(prefix, config).is_err());
+                let _ = self.parse_with_config(buf, config);
+                Err(err.with_headers(self.headers))
+            },
+            Ok(status) => Ok(status),
+        }
+    }
+
+    /// The offset in the parsed buffer at which the encapsulated data starts,
+    /// right after the ICAP head.
+    ///
+    /// This is `None` until the head has been completely parsed.
+    #[inline]
+    pub fn encapsulated_offset(&self) -> Option<usize> {
+        self.encapsulated_offset
+    }
+
+    /// The response code as an `IcapStatus`, once it has been parsed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use icaparse::IcapStatus;
+    ///
+    /// let buf = b"ICAP/1.0 100 Continue\r\n\r\n";
+    /// let mut headers = [icaparse::EMPTY_HEADER; 4];
+    /// let mut res = icaparse::Response::new(&mut headers);
+    /// res.parse(buf).unwrap();
+    /// assert_eq!(res.status(), Some(IcapStatus::Continue));
+    /// ```
+    #[inline]
+    pub fn status(&self) -> Option<IcapStatus> {
+        self.code.map(IcapStatus::from)
+    }
+
+    /// Parse the value of the `ISTag` header, if present.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let buf = b"ICAP/1.0 204 No Content\r\nISTag: \"W3E4R7U9-L2E4-2\"\r\nEncapsulated: null-body=0\r\n\r\n";
+    /// let mut headers = [icaparse::EMPTY_HEADER; 4];
+    /// let mut res = icaparse::Response::new(&mut headers);
+    /// res.parse(buf).unwrap();
+    /// assert_eq!(res.istag().unwrap().unwrap().as_str(), "W3E4R7U9-L2E4-2");
+    /// ```
+    pub fn istag(&self) -> result::Result<Option<ISTag<'b>>, Error> {
+        match find_header(self.headers, "ISTag") {
+            Some(h) => ISTag::parse(h.value).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Parse the headers of a reply to an `OPTIONS` request.
+    ///
+    /// See `OptionsResponse` for the headers and an example.
+    #[inline]
+    pub fn options(&self) -> result::Result<OptionsResponse<'b>, Error> {
+        OptionsResponse::from_headers(self.headers)
     }
 }
 
@@ -473,23 +1313,9 @@ fn parse_version(bytes: &mut Bytes) -> Result<u8> {
     }
 }
 
-fn find_section_start(encapsulated: &str, section: &str) -> Option<usize> {
-    if let Some(x) = encapsulated.find(section) {
-        let start = x + section.len();
-        let end = match encapsulated[start..encapsulated.len()].find(',') {
-            Some(i) => start + i,
-            None => encapsulated.len()
-        };
-        encapsulated[start..end].parse::<usize>().ok()
-    }
-    else {
-        None
-    }
-}
-
 /// Describes a section of the encapsulated data
 /// as listed in the Encapsulated header
-#[derive(Debug, Hash, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
 pub struct EncapsulationSection {
     /// The type of data in the section
     name: SectionType,
@@ -501,10 +1327,23 @@ impl EncapsulationSection {
     /// provides a constructor for method for the type
   pub fn new(name: SectionType, start: usize) -> EncapsulationSection {
       EncapsulationSection {
-          name: name,
-          start: start
+          name,
+          start
       }
   }
+
+    /// The type of data in the section.
+    #[inline]
+    pub fn name(&self) -> SectionType {
+        self.name
+    }
+
+    /// The start of the section, relative to the start of the encapsulated
+    /// data.
+    #[inline]
+    pub fn start(&self) -> usize {
+        self.start
+    }
 }
 
 impl fmt::Display for EncapsulationSection {
@@ -514,7 +1353,7 @@ impl fmt::Display for EncapsulationSection {
 }
 
 /// Possible sections of the encapsulated icap data
-#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub enum SectionType {
     /// Null Body Section
     NullBody,
@@ -530,53 +1369,436 @@ pub enum SectionType {
     OptionsBody
 }
 
-fn parse_encapsulated(header: &[u8], encapsulated: &[u8]) -> HashMap<SectionType, Vec<u8>> {
-    let mut sections = Vec::new();
-    {
-        let mut add_section = |section, start| sections.push(EncapsulationSection::new(section, start));
+impl SectionType {
+    /// The entity name of the section in the Encapsulated header, such as
+    /// `req-hdr`.
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            SectionType::NullBody => "null-body",
+            SectionType::RequestHeader => "req-hdr",
+            SectionType::RequestBody => "req-body",
+            SectionType::ResponseHeader => "res-hdr",
+            SectionType::ResponseBody => "res-body",
+            SectionType::OptionsBody => "opt-body",
+        }
+    }
 
-        if let Ok(encapsulated) = str::from_utf8(header) {
-            if let Some(section_start) = find_section_start(encapsulated, "req-hdr=") {
-                add_section(SectionType::RequestHeader, section_start);
-            }
-            if let Some(section_start) = find_section_start(encapsulated, "req-body=") {
-                add_section(SectionType::ResponseBody, section_start);
-            }
-            if let Some(section_start) = find_section_start(encapsulated, "null-body=") {
-                add_section(SectionType::NullBody, section_start);
-            }
-            if let Some(section_start) = find_section_start(encapsulated, "res-hdr=") {
-                add_section(SectionType::ResponseHeader, section_start);
-            }
-            if let Some(section_start) = find_section_start(encapsulated, "res-body=") {
-                add_section(SectionType::ResponseBody, section_start);
-            }
-            if let Some(section_start) = find_section_start(encapsulated, "opt-body=") {
-                add_section(SectionType::OptionsBody, section_start);
-            }
+    /// Returns true for the body sections, including `null-body`.
+    #[inline]
+    pub fn is_body(&self) -> bool {
+        !matches!(*self, SectionType::RequestHeader | SectionType::ResponseHeader)
+    }
+
+    #[inline]
+    fn from_entity(name: &[u8]) -> Option<SectionType> {
+        match name {
+            b"req-hdr" => Some(SectionType::RequestHeader),
+            b"res-hdr" => Some(SectionType::ResponseHeader),
+            b"req-body" => Some(SectionType::RequestBody),
+            b"res-body" => Some(SectionType::ResponseBody),
+            b"opt-body" => Some(SectionType::OptionsBody),
+            b"null-body" => Some(SectionType::NullBody),
+            _ => None,
         }
     }
-    sections.sort_by(|a, b| a.start.cmp(&b.start));
-    let mut hm = HashMap::new();
-    let mut iter = sections.iter().peekable();
-    loop {
-        let x = iter.next();
-        let y = iter.peek();
-        if x.is_some() {
-            let x = x.unwrap();
-            if y.is_some() {
-                let y = y.unwrap();
-                hm.insert(x.name.clone(), encapsulated[x.start..y.start].to_vec());
-            }
-            else {
-                hm.insert(x.name.clone(), encapsulated[x.start..encapsulated.len()].to_vec());
+
+    /// The position the section must take in the Encapsulated header.
+    #[inline]
+    fn rank(&self) -> u8 {
+        match *self {
+            SectionType::RequestHeader => 0,
+            SectionType::ResponseHeader => 1,
+            _ => 2,
+        }
+    }
+}
+
+impl fmt::Display for SectionType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The most sections an Encapsulated header can list: a request header, a
+/// response header, and one body.
+const MAX_SECTIONS: usize = 3;
+
+/// A parsed Encapsulated header.
+///
+/// From [RFC 3507](https://tools.ietf.org/html/rfc3507#section-4.4.1):
+///
+/// > ```notrust
+/// > encapsulated_list: [reqhdr] [reshdr] resbody
+/// > reqhdr  = "req-hdr" "=" (decimal integer)
+/// > reshdr  = "res-hdr" "=" (decimal integer)
+/// > resbody = { "req-body" | "res-body" | "opt-body" | "null-body" } "=" (decimal integer)
+/// > ```
+///
+/// Every header lists exactly one body section, which comes last. The first
+/// section starts at offset 0, and the offsets of the sections must be
+/// strictly increasing.
+///
+/// # Example
+///
+/// ```
+/// use icaparse::{EncapsulatedHeader, SectionType};
+///
+/// let header = EncapsulatedHeader::parse(b"req-hdr=0, res-hdr=137, res-body=296").unwrap();
+/// assert_eq!(header.len(), 3);
+/// assert_eq!(header.get(SectionType::ResponseBody), Some(296));
+///
+/// assert_eq!(EncapsulatedHeader::parse(b"req-body=0, res-body=10"),
+///            Err(icaparse::Error::Encapsulated));
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct EncapsulatedHeader {
+    list: [EncapsulationSection; MAX_SECTIONS],
+    len: usize,
+}
+
+impl EncapsulatedHeader {
+    /// Parse the value of an Encapsulated header.
+    pub fn parse(value: &[u8]) -> result::Result<EncapsulatedHeader, Error> {
+        let mut header = EncapsulatedHeader {
+            list: [EncapsulationSection::new(SectionType::NullBody, 0); MAX_SECTIONS],
+            len: 0,
+        };
+        let mut rest = value;
+        loop {
+            rest = trim_ows(rest);
+            let name_len = rest.iter().position(|&b| b == b'=').ok_or(Error::Encapsulated)?;
+            let name = SectionType::from_entity(&rest[..name_len]).ok_or(Error::Encapsulated)?;
+            rest = &rest[name_len + 1..];
+            let digits = rest.iter().take_while(|b| b.is_ascii_digit()).count();
+            let start = parse_decimal(&rest[..digits]).ok_or(Error::Encapsulated)?;
+            header.push(EncapsulationSection::new(name, start))?;
+            match trim_ows(&rest[digits..]).split_first() {
+                Some((&b',', tail)) => rest = tail,
+                Some(_) => return Err(Error::Encapsulated),
+                None => break,
             }
         }
-        else {
+        if header.has_body() {
+            Ok(header)
+        } else {
+            Err(Error::Encapsulated)
+        }
+    }
+
+    #[inline]
+    fn has_body(&self) -> bool {
+        self.as_slice().last().is_some_and(|s| s.name.is_body())
+    }
+
+    /// Adds a section, checking it can follow the ones already listed.
+    #[inline]
+    fn push(&mut self, section: EncapsulationSection) -> result::Result<(), Error> {
+        let valid = match self.as_slice().last() {
+            Some(last) => !last.name.is_body() && section.name.rank() > last.name.rank() &&
+                section.start > last.start,
+            // the encapsulated data starts with the first section
+            None => section.start == 0,
+        };
+        if !valid {
+            return Err(Error::Encapsulated);
+        }
+        self.list[self.len] = section;
+        self.len += 1;
+        Ok(())
+    }
+
+    /// The number of sections listed.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if no sections were listed.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The listed sections, in order.
+    #[inline]
+    pub fn as_slice(&self) -> &[EncapsulationSection] {
+        &self.list[..self.len]
+    }
+
+    /// Iterates over the listed sections, in order.
+    #[inline]
+    pub fn iter(&self) -> slice::Iter<'_, EncapsulationSection> {
+        self.as_slice().iter()
+    }
+
+    /// Finds the start of the section of the given type.
+    #[inline]
+    pub fn get(&self, name: SectionType) -> Option<usize> {
+        self.iter().find(|s| s.name == name).map(|s| s.start)
+    }
+
+    /// The body section, which is always the last one listed.
+    #[inline]
+    pub fn body(&self) -> EncapsulationSection {
+        self.list[self.len - 1]
+    }
+}
+
+impl<'a> IntoIterator for &'a EncapsulatedHeader {
+    type Item = &'a EncapsulationSection;
+    type IntoIter = slice::Iter<'a, EncapsulationSection>;
+
+    fn into_iter(self) -> slice::Iter<'a, EncapsulationSection> {
+        self.iter()
+    }
+}
+
+/// Returns true for optional whitespace, which includes the line breaks kept
+/// in header values with obsolete line folding.
+#[inline]
+pub(crate) fn is_ows(b: u8) -> bool {
+    b == b' ' || b == b'\t' || b == b'\r' || b == b'\n'
+}
+
+/// Trims optional whitespace from both ends of a header value, or of an item
+/// of one.
+#[inline]
+pub(crate) fn trim_ows(mut value: &[u8]) -> &[u8] {
+    while let Some((&b, rest)) = value.split_first() {
+        if !is_ows(b) {
             break;
         }
+        value = rest;
+    }
+    while let Some((&b, rest)) = value.split_last() {
+        if !is_ows(b) {
+            break;
+        }
+        value = rest;
+    }
+    value
+}
+
+/// Like `trim_ows`, for a header value that has been checked to be text.
+#[inline]
+pub(crate) fn trim_ows_str(value: &str) -> &str {
+    value.trim_matches(|c: char| c.is_ascii() && is_ows(c as u8))
+}
+
+/// A section of the encapsulated data, borrowed from the parsed buffer.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Section<'a> {
+    /// The type of data in the section.
+    pub name: SectionType,
+    /// The offset of the section, relative to the start of the encapsulated
+    /// data, as listed in the Encapsulated header.
+    pub offset: usize,
+    /// The bytes of the section.
+    pub value: &'a [u8],
+}
+
+const EMPTY_SECTION: Section<'static> = Section { name: SectionType::NullBody, offset: 0, value: b"" };
+
+/// An owned copy of the encapsulated sections.
+#[cfg(feature = "std")]
+type SectionMap = HashMap<SectionType, Vec<u8>>;
+#[cfg(all(feature = "alloc", not(feature = "std")))]
+type SectionMap = BTreeMap<SectionType, Vec<u8>>;
+
+/// The sections of the encapsulated data, borrowed from the parsed buffer.
+///
+/// Sections are kept in a fixed-size array in the order of their offsets, so
+/// no allocation is needed to hold them.
+///
+/// # Example
+///
+/// ```
+/// let buf = b"RESPMOD / ICAP/1.0\r\nEncapsulated: res-hdr=0, null-body=19\r\n\r\nHTTP/1.1 200 OK\r\n\r\n";
+/// let mut headers = [icaparse::EMPTY_HEADER; 4];
+/// let mut req = icaparse::Request::new(&mut headers);
+/// req.parse(buf).unwrap();
+/// let sections = req.sections.unwrap();
+/// assert_eq!(sections.len(), 2);
+/// assert_eq!(sections.get(icaparse::SectionType::ResponseHeader),
+///            Some(&b"HTTP/1.1 200 OK\r\n\r\n"[..]));
+/// ```
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Sections<'a> {
+    list: [Section<'a>; MAX_SECTIONS],
+    len: usize,
+    body_complete: bool,
+    preview_end: bool,
+}
+
+impl<'a> Sections<'a> {
+    /// Creates an empty set of sections.
+    #[inline]
+    pub fn new() -> Sections<'a> {
+        Sections {
+            list: [EMPTY_SECTION; MAX_SECTIONS],
+            len: 0,
+            body_complete: false,
+            preview_end: false,
+        }
+    }
+
+    /// The number of sections listed.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if no sections were listed.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns true if the whole body section was contained in the parsed
+    /// buffer, up to and including its last chunk.
+    ///
+    /// A `null-body` is always complete. An incomplete body holds every byte
+    /// that followed the ICAP head, and the rest of it can be read with a
+    /// `ChunkedDecoder`.
+    ///
+    /// A preview body is complete only if its last chunk carries the `ieof`
+    /// extension; see `Sections::is_preview_end`.
+    #[inline]
+    pub fn is_body_complete(&self) -> bool {
+        self.body_complete
+    }
+
+    /// Returns true if the body is a preview that ended without the `ieof`
+    /// extension, so that the rest of it follows once the server has replied
+    /// `ICAP/1.0 100 Continue`.
+    ///
+    /// The rest of the body can be read with a `ChunkedDecoder`.
+    #[inline]
+    pub fn is_preview_end(&self) -> bool {
+        self.preview_end
+    }
+
+    /// The listed sections, in the order of their offsets.
+    #[inline]
+    pub fn as_slice(&self) -> &[Section<'a>] {
+        &self.list[..self.len]
+    }
+
+    /// Iterates over the listed sections, in the order of their offsets.
+    #[inline]
+    pub fn iter(&self) -> slice::Iter<'_, Section<'a>> {
+        self.as_slice().iter()
+    }
+
+    /// Finds the section of the given type.
+    #[inline]
+    pub fn section(&self, name: SectionType) -> Option<&Section<'a>> {
+        self.iter().find(|s| s.name == name)
+    }
+
+    /// Finds the bytes of the section of the given type.
+    #[inline]
+    pub fn get(&self, name: SectionType) -> Option<&'a [u8]> {
+        self.section(name).map(|s| s.value)
+    }
+
+    /// Copies the sections into an owned map.
+    ///
+    /// Parsing never copies the sections; this is only done when asked for.
+    /// A `BTreeMap` is returned instead without the `std` feature.
+    #[cfg(feature = "alloc")]
+    pub fn to_map(&self) -> SectionMap {
+        self.iter().map(|s| (s.name, s.value.to_vec())).collect()
+    }
+}
+
+impl<'a> Default for Sections<'a> {
+    fn default() -> Sections<'a> {
+        Sections::new()
+    }
+}
+
+impl<'a, 'b> IntoIterator for &'b Sections<'a> {
+    type Item = &'b Section<'a>;
+    type IntoIter = slice::Iter<'b, Section<'a>>;
+
+    fn into_iter(self) -> slice::Iter<'b, Section<'a>> {
+        self.iter()
+    }
+}
+
+/// Splits the encapsulated data into the sections listed in the Encapsulated
+/// header, returning them along with the number of bytes that belong to the
+/// message.
+///
+/// The result is partial until every encapsulated header section is in the
+/// buffer. A chunked body ends with its last chunk, and any bytes following
+/// it are not part of the message. If the last chunk is not in the buffer
+/// yet, the rest of the buffer is taken as the body. A preview body also ends
+/// with a zero-sized chunk lacking the `ieof` extension.
+fn parse_encapsulated<'a>(value: &[u8], encapsulated: &'a [u8], config: &ParserConfig, preview: bool)
+        -> Result<(Sections<'a>, usize)> {
+    let header = EncapsulatedHeader::parse(value)?;
+    if config.max_encapsulated_offset.is_some_and(|max| header.body().start > max) {
+        return Err(Error::EncapsulatedOffsetTooLarge);
+    }
+    if header.body().start > encapsulated.len() {
+        return Ok(Status::Partial);
+    }
+    let mut sections = Sections::new();
+    let mut end = 0;
+    let list = header.as_slice();
+    for (i, section) in list.iter().enumerate() {
+        let start = section.start;
+        end = match list.get(i + 1) {
+            Some(next) => {
+                if !ends_with_empty_line(&encapsulated[start..next.start]) {
+                    return Err(Error::SectionOffset);
+                }
+                next.start
+            },
+            None if section.name == SectionType::NullBody => {
+                sections.body_complete = true;
+                start
+            },
+            None if config.skip_body => start,
+            None => {
+                let (len, decoder) = chunked_body_len(&encapsulated[start..], config, preview)?;
+                sections.body_complete = decoder.is_done();
+                sections.preview_end = decoder.is_preview_end();
+                start + len
+            },
+        };
+        sections.list[i] = Section {
+            name: section.name,
+            offset: start,
+            value: &encapsulated[start..end],
+        };
+    }
+    sections.len = list.len();
+    Ok(Status::Complete((sections, end)))
+}
+
+/// Whether an encapsulated HTTP header ends with the empty line ending it.
+#[inline]
+fn ends_with_empty_line(value: &[u8]) -> bool {
+    value.ends_with(b"\r\n\r\n") || value.ends_with(b"\n\n")
+}
+
+/// Finds the length of a chunked body, along with the decoder telling how it
+/// ended, if it did.
+fn chunked_body_len(body: &[u8], config: &ParserConfig, preview: bool)
+        -> result::Result<(usize, ChunkedDecoder), Error> {
+    let decoder = if preview { ChunkedDecoder::preview() } else { ChunkedDecoder::new() };
+    let mut decoder = decoder.with_config(config);
+    let mut pos = 0;
+    while !decoder.is_done() && !decoder.is_preview_end() {
+        match decoder.decode(&body[pos..])? {
+            Status::Complete((len, _)) => pos += len,
+            Status::Partial => return Ok((body.len(), decoder)),
+        }
     }
-    hm
+    Ok((pos, decoder))
 }
 
 /// From [RFC 7230](https://tools.ietf.org/html/rfc7230):
@@ -612,7 +1834,7 @@ fn parse_reason<'a>(bytes: &mut Bytes<'a>) -> Result<&'a str> {
                 // all bytes up till `i` must have been HTAB / SP / VCHAR
                 str::from_utf8_unchecked(bytes.slice_skip(1))
             }));
-        } else if !((b >= 0x20 && b <= 0x7E) || b == b'\t') {
+        } else if !((0x20..=0x7E).contains(&b) || b == b'\t') {
             return Err(Error::Status);
         }
     }
@@ -635,9 +1857,9 @@ fn parse_token<'a>(bytes: &mut Bytes<'a>) -> Result<&'a str> {
 
 #[inline]
 fn parse_code(bytes: &mut Bytes) -> Result<u16> {
-    let hundreds = expect!(bytes.next() == b'0'...b'9' => Err(Error::Status));
-    let tens = expect!(bytes.next() == b'0'...b'9' => Err(Error::Status));
-    let ones = expect!(bytes.next() == b'0'...b'9' => Err(Error::Status));
+    let hundreds = expect!(bytes.next() == b'0'..=b'9' => Err(Error::Status));
+    let tens = expect!(bytes.next() == b'0'..=b'9' => Err(Error::Status));
+    let ones = expect!(bytes.next() == b'0'..=b'9' => Err(Error::Status));
 
     Ok(Status::Complete(u16::from(hundreds - b'0') * 100 +
                         u16::from(tens - b'0') * 10 +
@@ -665,12 +1887,13 @@ fn parse_code(bytes: &mut Bytes) -> Result<u16> {
 pub fn parse_headers<'b: 'h, 'h>(src: &'b [u8], mut dst: &'h mut [Header<'b>])
     -> Result<(usize, &'h [Header<'b>])> {
     let mut iter = Bytes::new(src);
-    let pos = complete!(parse_headers_iter(&mut dst, &mut iter));
+    let pos = complete!(parse_headers_iter(&mut dst, &mut iter, ParseMode::Standard));
     Ok(Status::Complete((pos, dst)))
 }
 
 #[inline]
-fn parse_headers_iter<'a, 'b>(headers: &mut &mut [Header<'a>], bytes: &'b mut Bytes<'a>)
+#[allow(clippy::never_loop)]
+fn parse_headers_iter<'a>(headers: &mut &mut [Header<'a>], bytes: &mut Bytes<'a>, mode: ParseMode)
     -> Result<usize> {
     let mut num_headers: usize = 0;
     let mut count: usize = 0;
@@ -708,75 +1931,115 @@ fn parse_headers_iter<'a, 'b>(headers: &mut &mut [Header<'a>], bytes: &'b mut By
                         str::from_utf8_unchecked(bytes.slice_skip(1))
                     };
                     break 'name;
+                } else if mode == ParseMode::Lenient && (b == b' ' || b == b'\t') {
+                    count += bytes.pos();
+                    header.name = unsafe {
+                        str::from_utf8_unchecked(bytes.slice_skip(1))
+                    };
+                    // only more whitespace may come before the colon
+                    loop {
+                        let b = next!(bytes);
+                        if b == b':' {
+                            count += bytes.pos();
+                            bytes.slice();
+                            break 'name;
+                        } else if b != b' ' && b != b'\t' {
+                            return Err(Error::HeaderName);
+                        }
+                    }
                 } else if !is_header_name_token(b) {
                     return Err(Error::HeaderName);
                 }
             }
 
             let mut b;
-
-            'value: loop {
-
-                // eat white space between colon and value
-                'whitespace: loop {
-                    b = next!(bytes);
-                    if b == b' ' || b == b'\t' {
-                        count += bytes.pos();
-                        bytes.slice();
-                        continue 'whitespace;
-                    } else {
-                        if !is_header_value_token(b) {
-                            break 'value;
+            // whether the value has started, as it may start on a folded line
+            let mut started = false;
+
+            'line: loop {
+                'value: loop {
+
+                    // eat white space between colon and value
+                    'whitespace: loop {
+                        b = next!(bytes);
+                        if b == b' ' || b == b'\t' {
+                            if !started {
+                                count += bytes.pos();
+                                bytes.slice();
+                            }
+                            continue 'whitespace;
+                        } else {
+                            if !is_header_value_token(b) {
+                                break 'value;
+                            }
+                            started = true;
+                            break 'whitespace;
                         }
-                        break 'whitespace;
                     }
-                }
-
-                // parse value till EOL
 
-
-
-                macro_rules! check {
-                    ($bytes:ident, $i:ident) => ({
-                        b = $bytes.$i();
+                    // parse value till EOL
+
+
+
+                    macro_rules! check {
+                        ($bytes:ident, $i:ident) => ({
+                            b = $bytes.$i();
+                            if !is_header_value_token(b) {
+                                break 'value;
+                            }
+                        });
+                        ($bytes:ident) => ({
+                            check!($bytes, _0);
+                            check!($bytes, _1);
+                            check!($bytes, _2);
+                            check!($bytes, _3);
+                            check!($bytes, _4);
+                            check!($bytes, _5);
+                            check!($bytes, _6);
+                            check!($bytes, _7);
+                        })
+                    }
+                    while let Some(mut bytes8) = bytes.next_8() {
+                        check!(bytes8);
+                    }
+                    loop {
+                        b = next!(bytes);
                         if !is_header_value_token(b) {
                             break 'value;
                         }
-                    });
-                    ($bytes:ident) => ({
-                        check!($bytes, _0);
-                        check!($bytes, _1);
-                        check!($bytes, _2);
-                        check!($bytes, _3);
-                        check!($bytes, _4);
-                        check!($bytes, _5);
-                        check!($bytes, _6);
-                        check!($bytes, _7);
-                    })
-                }
-                while let Some(mut bytes8) = bytes.next_8() {
-                    check!(bytes8);
-                }
-                loop {
-                    b = next!(bytes);
-                    if !is_header_value_token(b) {
-                        break 'value;
                     }
                 }
-            }
 
-            //found_ctl
-            if b == b'\r' {
-                expect!(bytes.next() == b'\n' => Err(Error::HeaderValue));
-                count += bytes.pos();
-                header.value = bytes.slice_skip(2);
-            } else if b == b'\n' {
+                //found_ctl
+                let skip = if b == b'\r' {
+                    expect!(bytes.next() == b'\n' => Err(Error::HeaderValue));
+                    2
+                } else if b == b'\n' {
+                    1
+                } else {
+                    return Err(Error::HeaderValue);
+                };
+
+                // with obsolete line folding, a line starting with
+                // whitespace continues the value
+                if mode == ParseMode::Lenient {
+                    match bytes.peek() {
+                        Some(b' ') | Some(b'\t') => {
+                            if !started {
+                                // drop the line break before the value
+                                count += bytes.pos();
+                                bytes.slice();
+                            }
+                            continue 'line;
+                        },
+                        Some(..) => {},
+                        None => return Ok(Status::Partial),
+                    }
+                }
                 count += bytes.pos();
-                header.value = bytes.slice_skip(1);
-            } else {
-                return Err(Error::HeaderValue);
+                header.value = bytes.slice_skip(skip);
+                break 'line;
             }
-
         }
     } // drop iter
 
@@ -798,16 +2061,39 @@ fn parse_headers_iter<'a, 'b>(headers: &mut &mut [Header<'a>], bytes: &'b mut By
 /// ```
 pub fn parse_chunk_size(buf: &[u8])
         -> result::Result<Status<(usize, u64)>, InvalidChunkSize> {
+    match parse_chunk_head(buf)? {
+        Status::Complete((pos, size, _)) => Ok(Status::Complete((pos, size))),
+        Status::Partial => Ok(Status::Partial),
+    }
+}
+
+/// Parse a buffer of bytes as a chunk size, along with its extensions.
+///
+/// This behaves like `parse_chunk_size`, but also returns the extensions of
+/// the chunk size line, such as the ICAP `ieof` extension that terminates a
+/// preview.
+///
+/// # Example
+///
+/// ```
+/// let buf = b"0; ieof\r\n\r\n";
+/// let (pos, size, extensions) = icaparse::parse_chunk_head(buf).unwrap().unwrap();
+/// assert_eq!((pos, size), (9, 0));
+/// assert!(extensions.ieof());
+/// ```
+pub fn parse_chunk_head(buf: &[u8]) -> ChunkResult<(usize, u64, ChunkExtensions<'_>)> {
     const RADIX: u64 = 16;
     let mut bytes = Bytes::new(buf);
     let mut size = 0;
     let mut in_chunk_size = true;
     let mut in_ext = false;
     let mut count = 0;
+    let mut ext_start = 0;
+    let mut ext_end = 0;
     loop {
         let b = next!(bytes);
         match b {
-            b'0'...b'9' if in_chunk_size => {
+            b'0'..=b'9' if in_chunk_size => {
                 if count > 15 {
                     return Err(InvalidChunkSize);
                 }
@@ -815,7 +2101,7 @@ pub fn parse_chunk_size(buf: &[u8])
                 size *= RADIX;
                 size += u64::from(b - b'0');
             },
-            b'a'...b'f' if in_chunk_size => {
+            b'a'..=b'f' if in_chunk_size => {
                 if count > 15 {
                     return Err(InvalidChunkSize);
                 }
@@ -823,7 +2109,7 @@ pub fn parse_chunk_size(buf: &[u8])
                 size *= RADIX;
                 size += u64::from(b + 10 - b'a');
             }
-            b'A'...b'F' if in_chunk_size => {
+            b'A'..=b'F' if in_chunk_size => {
                 if count > 15 {
                     return Err(InvalidChunkSize);
                 }
@@ -831,7 +2117,13 @@ pub fn parse_chunk_size(buf: &[u8])
                 size *= RADIX;
                 size += u64::from(b + 10 - b'A');
             }
+            // The size needs at least one digit, or a missing size would be
+            // read as the last chunk.
+            _ if count == 0 => return Err(InvalidChunkSize),
             b'\r' => {
+                if in_ext {
+                    ext_end = bytes.pos() - 1;
+                }
                 match next!(bytes) {
                     b'\n' => break,
                     _ => return Err(InvalidChunkSize),
@@ -841,6 +2133,7 @@ pub fn parse_chunk_size(buf: &[u8])
             b';' if !in_ext => {
                 in_ext = true;
                 in_chunk_size = false;
+                ext_start = bytes.pos();
             }
             // "Linear white space" is ignored between the chunk size and the
             // extension separator token (";") due to the "implied *LWS rule".
@@ -858,6 +2151,6 @@ pub fn parse_chunk_size(buf: &[u8])
             _ => return Err(InvalidChunkSize),
         }
     }
-    Ok(Status::Complete((bytes.pos(), size)))
+    Ok(Status::Complete((bytes.pos(), size, ChunkExtensions::new(&buf[ext_start..ext_end]))))
 }
 
@@ -0,0 +1,96 @@
+use core::fmt;
+
+use SectionType;
+
+/// An ICAP request method.
+///
+/// Methods are case-sensitive, so `reqmod` is an extension method rather than
+/// `IcapMethod::Reqmod`.
+///
+/// # Example
+///
+/// ```
+/// use icaparse::IcapMethod;
+///
+/// assert_eq!(IcapMethod::from("RESPMOD"), IcapMethod::Respmod);
+/// assert_eq!(IcapMethod::from("LOG"), IcapMethod::Extension("LOG"));
+/// assert!(!IcapMethod::Options.requires_encapsulated());
+/// ```
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum IcapMethod<'a> {
+    /// `REQMOD`: request modification.
+    Reqmod,
+    /// `RESPMOD`: response modification.
+    Respmod,
+    /// `OPTIONS`: learn about the configuration of a service.
+    Options,
+    /// Any other method.
+    Extension(&'a str),
+}
+
+impl<'a> IcapMethod<'a> {
+    /// The method as it appears on the request line.
+    #[inline]
+    pub fn as_str(&self) -> &'a str {
+        match *self {
+            IcapMethod::Reqmod => "REQMOD",
+            IcapMethod::Respmod => "RESPMOD",
+            IcapMethod::Options => "OPTIONS",
+            IcapMethod::Extension(method) => method,
+        }
+    }
+
+    /// Returns true if requests with this method must carry an `Encapsulated`
+    /// header.
+    ///
+    /// Only `OPTIONS` requests may omit it. Extension methods are assumed to
+    /// require it.
+    #[inline]
+    pub fn requires_encapsulated(&self) -> bool {
+        *self != IcapMethod::Options
+    }
+
+    /// Returns true if a request with this method may encapsulate the given
+    /// section.
+    ///
+    /// From [RFC 3507](https://tools.ietf.org/html/rfc3507#section-4.4.1):
+    ///
+    /// > ```notrust
+    /// > REQMOD  request  encapsulated_list: [reqhdr] reqbody
+    /// > RESPMOD request  encapsulated_list: [reqhdr] [reshdr] resbody
+    /// > OPTIONS request  encapsulated_list: [optbody]
+    /// > ```
+    ///
+    /// Extension methods allow every section.
+    pub fn allows_section(&self, name: SectionType) -> bool {
+        match *self {
+            IcapMethod::Reqmod => matches!(name, SectionType::RequestHeader |
+                                                 SectionType::RequestBody |
+                                                 SectionType::NullBody),
+            IcapMethod::Respmod => matches!(name, SectionType::RequestHeader |
+                                                  SectionType::ResponseHeader |
+                                                  SectionType::ResponseBody |
+                                                  SectionType::NullBody),
+            IcapMethod::Options => matches!(name, SectionType::OptionsBody |
+                                                  SectionType::NullBody),
+            IcapMethod::Extension(_) => true,
+        }
+    }
+}
+
+impl<'a> From<&'a str> for IcapMethod<'a> {
+    fn from(method: &'a str) -> IcapMethod<'a> {
+        match method {
+            "REQMOD" => IcapMethod::Reqmod,
+            "RESPMOD" => IcapMethod::Respmod,
+            "OPTIONS" => IcapMethod::Options,
+            _ => IcapMethod::Extension(method),
+        }
+    }
+}
+
+impl<'a> fmt::Display for IcapMethod<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
@@ -0,0 +1,246 @@
+use core::{result, str};
+use core::time::Duration;
+
+use {find_header, parse_decimal, trim_ows_str, EncapsulatedHeader, Error, Header, ISTag, IcapMethod};
+
+/// The typed headers of a reply to an `OPTIONS` request.
+///
+/// From [RFC 3507](https://tools.ietf.org/html/rfc3507#section-4.10.2), an
+/// ICAP server describes the service at the request URI through these
+/// headers, so that clients can configure themselves from its capabilities.
+/// Headers that are absent are `None`, or an empty list.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use icaparse::{IcapMethod, OptionsResponse, Transfer};
+///
+/// let buf = b"ICAP/1.0 200 OK\r\n\
+/// Methods: RESPMOD\r\n\
+/// ISTag: \"W3E4R7U9-L2E4-2\"\r\n\
+/// Options-TTL: 3600\r\n\
+/// Allow: 204\r\n\
+/// Preview: 1024\r\n\
+/// Transfer-Preview: *\r\n\
+/// Transfer-Ignore: jpg, jpeg, gif\r\n\
+/// Encapsulated: null-body=0\r\n\r\n";
+/// let mut headers = [icaparse::EMPTY_HEADER; 16];
+/// let mut res = icaparse::Response::new(&mut headers);
+/// res.parse(buf).unwrap();
+///
+/// let options = res.options().unwrap();
+/// assert!(options.supports(IcapMethod::Respmod));
+/// assert_eq!(options.options_ttl, Some(Duration::from_secs(3600)));
+/// assert!(options.allows(204));
+/// assert_eq!(options.preview, Some(1024));
+/// assert_eq!(options.transfer("GIF"), Some(Transfer::Ignore));
+/// assert_eq!(options.transfer("html"), Some(Transfer::Preview));
+/// ```
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct OptionsResponse<'a> {
+    /// The `Methods` header: the ICAP methods supported by the service.
+    pub methods: TokenList<'a>,
+    /// The `Service` header: a description of the ICAP server.
+    pub service: Option<&'a str>,
+    /// The `ISTag` header: the state of the service.
+    pub istag: Option<ISTag<'a>>,
+    /// The `Encapsulated` header of the reply.
+    pub encapsulated: Option<EncapsulatedHeader>,
+    /// The `Max-Connections` header: the maximum number of concurrent
+    /// connections the server supports.
+    pub max_connections: Option<usize>,
+    /// The `Options-TTL` header: how long this reply stays valid.
+    pub options_ttl: Option<Duration>,
+    /// The `Date` header: when the reply was sent.
+    ///
+    /// This is the text of the header, trimmed of whitespace; it is not
+    /// checked to be a valid HTTP-date. Parse it with a date crate, such as
+    /// `httpdate`, when the time itself is needed.
+    pub date: Option<&'a str>,
+    /// The `Service-ID` header: a short label for the service.
+    pub service_id: Option<&'a str>,
+    /// The `Allow` header: the optional status codes the server supports,
+    /// such as `204`.
+    pub allow: TokenList<'a>,
+    /// The `Preview` header: the number of bytes the server wants to preview.
+    pub preview: Option<usize>,
+    /// The `Transfer-Preview` header: file extensions that should be sent
+    /// with a preview.
+    pub transfer_preview: TokenList<'a>,
+    /// The `Transfer-Ignore` header: file extensions that should not be sent
+    /// to the server at all.
+    pub transfer_ignore: TokenList<'a>,
+    /// The `Transfer-Complete` header: file extensions that should be sent
+    /// to the server in full, without a preview.
+    pub transfer_complete: TokenList<'a>,
+}
+
+impl<'a> OptionsResponse<'a> {
+    /// Parses the OPTIONS headers out of the headers of a response.
+    ///
+    /// Returns `Error::HeaderValue` if one of the headers is malformed, such
+    /// as a non-numeric `Options-TTL` or an unquoted `ISTag`, and
+    /// `Error::Encapsulated` if the `Encapsulated` header is invalid.
+    pub fn from_headers(headers: &[Header<'a>]) -> result::Result<OptionsResponse<'a>, Error> {
+        let encapsulated = match find_header(headers, "Encapsulated") {
+            Some(h) => Some(EncapsulatedHeader::parse(h.value)?),
+            None => None,
+        };
+        Ok(OptionsResponse {
+            methods: list(headers, "Methods")?,
+            service: text(headers, "Service")?,
+            istag: match find_header(headers, "ISTag") {
+                Some(h) => Some(ISTag::parse(h.value)?),
+                None => None,
+            },
+            encapsulated,
+            max_connections: decimal(headers, "Max-Connections")?,
+            options_ttl: decimal(headers, "Options-TTL")?.map(|secs| Duration::from_secs(secs as u64)),
+            date: text(headers, "Date")?,
+            service_id: text(headers, "Service-ID")?,
+            allow: list(headers, "Allow")?,
+            preview: decimal(headers, "Preview")?,
+            transfer_preview: list(headers, "Transfer-Preview")?,
+            transfer_ignore: list(headers, "Transfer-Ignore")?,
+            transfer_complete: list(headers, "Transfer-Complete")?,
+        })
+    }
+
+    /// Returns true if the `Methods` header lists the given method.
+    #[inline]
+    pub fn supports(&self, method: IcapMethod) -> bool {
+        self.methods.iter().any(|token| IcapMethod::from(token) == method)
+    }
+
+    /// Returns true if the `Allow` header lists the given status code.
+    #[inline]
+    pub fn allows(&self, code: u16) -> bool {
+        allows(self.allow, code)
+    }
+
+    /// Decides how a file with the given extension should be sent to the
+    /// service, ignoring case.
+    ///
+    /// An extension listed explicitly in one of the `Transfer-*` headers takes
+    /// precedence over a `*` wildcard in another. Returns `None` if none of
+    /// the headers apply.
+    pub fn transfer(&self, extension: &str) -> Option<Transfer> {
+        let lists = [
+            (Transfer::Preview, self.transfer_preview),
+            (Transfer::Ignore, self.transfer_ignore),
+            (Transfer::Complete, self.transfer_complete),
+        ];
+        lists.iter().find(|&&(_, list)| list.contains(extension))
+            .or_else(|| lists.iter().find(|&&(_, list)| list.contains("*")))
+            .map(|&(transfer, _)| transfer)
+    }
+}
+
+/// How a client should send a file to an ICAP service, as advertised by the
+/// `Transfer-*` headers of an OPTIONS reply.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Transfer {
+    /// Send the file with a preview (`Transfer-Preview`).
+    Preview,
+    /// Do not send the file to the service (`Transfer-Ignore`).
+    Ignore,
+    /// Send the whole file, without a preview (`Transfer-Complete`).
+    Complete,
+}
+
+/// A comma separated header value, such as `Methods: REQMOD, RESPMOD`.
+///
+/// The list is empty if the header was absent.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct TokenList<'a> {
+    value: &'a str,
+}
+
+impl<'a> TokenList<'a> {
+    /// Creates a list from a raw header value.
+    #[inline]
+    pub fn new(value: &'a str) -> TokenList<'a> {
+        TokenList { value }
+    }
+
+    /// The raw header value.
+    #[inline]
+    pub fn as_str(&self) -> &'a str {
+        self.value
+    }
+
+    /// Returns true if the list has no items.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+
+    /// Iterates over the items of the list, with surrounding whitespace
+    /// removed and empty items skipped.
+    #[inline]
+    pub fn iter(&self) -> TokenListIter<'a> {
+        TokenListIter { inner: self.value.split(',') }
+    }
+
+    /// Returns true if the list contains the given item, ignoring case.
+    #[inline]
+    pub fn contains(&self, item: &str) -> bool {
+        self.iter().any(|token| token.eq_ignore_ascii_case(item))
+    }
+}
+
+impl<'a> IntoIterator for TokenList<'a> {
+    type Item = &'a str;
+    type IntoIter = TokenListIter<'a>;
+
+    fn into_iter(self) -> TokenListIter<'a> {
+        self.iter()
+    }
+}
+
+/// An iterator over the items of a `TokenList`.
+#[derive(Clone, Debug)]
+pub struct TokenListIter<'a> {
+    inner: str::Split<'a, char>,
+}
+
+impl<'a> Iterator for TokenListIter<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        self.inner.by_ref()
+            .map(trim_ows_str)
+            .find(|token| !token.is_empty())
+    }
+}
+
+#[inline]
+fn text<'a>(headers: &[Header<'a>], name: &str) -> result::Result<Option<&'a str>, Error> {
+    match find_header(headers, name) {
+        Some(h) => match str::from_utf8(h.value) {
+            Ok(value) => Ok(Some(trim_ows_str(value))),
+            Err(_) => Err(Error::HeaderValue),
+        },
+        None => Ok(None),
+    }
+}
+
+#[inline]
+pub(crate) fn list<'a>(headers: &[Header<'a>], name: &str) -> result::Result<TokenList<'a>, Error> {
+    Ok(TokenList::new(text(headers, name)?.unwrap_or("")))
+}
+
+#[inline]
+fn decimal(headers: &[Header], name: &str) -> result::Result<Option<usize>, Error> {
+    match find_header(headers, name) {
+        Some(h) => parse_decimal(h.value).map(Some).ok_or(Error::HeaderValue),
+        None => Ok(None),
+    }
+}
+
+/// Checks an `Allow` header value for a status code.
+#[inline]
+pub(crate) fn allows(allow: TokenList, code: u16) -> bool {
+    allow.iter().any(|token| token.parse() == Ok(code))
+}
@@ -0,0 +1,167 @@
+use core::fmt;
+
+/// An ICAP status code.
+///
+/// The codes defined by [RFC 3507](https://tools.ietf.org/html/rfc3507#section-4.3.3),
+/// along with `206 Partial Content` from the ICAP extensions, have their own
+/// variant. Any other code is kept as `IcapStatus::Other`, so converting from
+/// and back to a `u16` is lossless.
+///
+/// # Example
+///
+/// ```
+/// use icaparse::IcapStatus;
+///
+/// let status = IcapStatus::from(204);
+/// assert_eq!(status, IcapStatus::NoContent);
+/// assert!(status.is_success());
+/// assert_eq!(status.canonical_reason(), Some("No Content"));
+/// assert_eq!(u16::from(status), 204);
+///
+/// assert_eq!(IcapStatus::from(299), IcapStatus::Other(299));
+/// ```
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum IcapStatus {
+    /// `100 Continue`, after a preview.
+    Continue,
+    /// `200 OK`.
+    Ok,
+    /// `204 No Content`: no modifications needed.
+    NoContent,
+    /// `206 Partial Content`: use the original body from an offset.
+    PartialContent,
+    /// `400 Bad Request`.
+    BadRequest,
+    /// `404 ICAP Service Not Found`.
+    ServiceNotFound,
+    /// `405 Method Not Allowed For Service`.
+    MethodNotAllowed,
+    /// `408 Request Timeout`.
+    RequestTimeout,
+    /// `418 Bad Composition`: the server needs the encapsulated sections
+    /// composed differently.
+    BadComposition,
+    /// `500 Server Error`.
+    ServerError,
+    /// `501 Method Not Implemented`.
+    MethodNotImplemented,
+    /// `502 Bad Gateway`.
+    BadGateway,
+    /// `503 Service Overloaded`.
+    ServiceOverloaded,
+    /// `505 ICAP Version Not Supported`.
+    VersionNotSupported,
+    /// Any other status code.
+    ///
+    /// Use `IcapStatus::from` to build a status from a code, as this variant
+    /// never holds a code that has its own variant.
+    Other(u16),
+}
+
+impl IcapStatus {
+    /// The numeric status code.
+    #[inline]
+    pub fn as_u16(&self) -> u16 {
+        match *self {
+            IcapStatus::Continue => 100,
+            IcapStatus::Ok => 200,
+            IcapStatus::NoContent => 204,
+            IcapStatus::PartialContent => 206,
+            IcapStatus::BadRequest => 400,
+            IcapStatus::ServiceNotFound => 404,
+            IcapStatus::MethodNotAllowed => 405,
+            IcapStatus::RequestTimeout => 408,
+            IcapStatus::BadComposition => 418,
+            IcapStatus::ServerError => 500,
+            IcapStatus::MethodNotImplemented => 501,
+            IcapStatus::BadGateway => 502,
+            IcapStatus::ServiceOverloaded => 503,
+            IcapStatus::VersionNotSupported => 505,
+            IcapStatus::Other(code) => code,
+        }
+    }
+
+    /// The canonical reason-phrase of a known status code.
+    #[inline]
+    pub fn canonical_reason(&self) -> Option<&'static str> {
+        match *self {
+            IcapStatus::Continue => Some("Continue"),
+            IcapStatus::Ok => Some("OK"),
+            IcapStatus::NoContent => Some("No Content"),
+            IcapStatus::PartialContent => Some("Partial Content"),
+            IcapStatus::BadRequest => Some("Bad Request"),
+            IcapStatus::ServiceNotFound => Some("ICAP Service Not Found"),
+            IcapStatus::MethodNotAllowed => Some("Method Not Allowed For Service"),
+            IcapStatus::RequestTimeout => Some("Request Timeout"),
+            IcapStatus::BadComposition => Some("Bad Composition"),
+            IcapStatus::ServerError => Some("Server Error"),
+            IcapStatus::MethodNotImplemented => Some("Method Not Implemented"),
+            IcapStatus::BadGateway => Some("Bad Gateway"),
+            IcapStatus::ServiceOverloaded => Some("Service Overloaded"),
+            IcapStatus::VersionNotSupported => Some("ICAP Version Not Supported"),
+            IcapStatus::Other(_) => None,
+        }
+    }
+
+    /// Returns true for `1xx` codes.
+    #[inline]
+    pub fn is_informational(&self) -> bool {
+        (100..200).contains(&self.as_u16())
+    }
+
+    /// Returns true for `2xx` codes.
+    #[inline]
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.as_u16())
+    }
+
+    /// Returns true for `4xx` codes.
+    #[inline]
+    pub fn is_client_error(&self) -> bool {
+        (400..500).contains(&self.as_u16())
+    }
+
+    /// Returns true for `5xx` codes.
+    #[inline]
+    pub fn is_server_error(&self) -> bool {
+        (500..600).contains(&self.as_u16())
+    }
+}
+
+impl From<u16> for IcapStatus {
+    fn from(code: u16) -> IcapStatus {
+        match code {
+            100 => IcapStatus::Continue,
+            200 => IcapStatus::Ok,
+            204 => IcapStatus::NoContent,
+            206 => IcapStatus::PartialContent,
+            400 => IcapStatus::BadRequest,
+            404 => IcapStatus::ServiceNotFound,
+            405 => IcapStatus::MethodNotAllowed,
+            408 => IcapStatus::RequestTimeout,
+            418 => IcapStatus::BadComposition,
+            500 => IcapStatus::ServerError,
+            501 => IcapStatus::MethodNotImplemented,
+            502 => IcapStatus::BadGateway,
+            503 => IcapStatus::ServiceOverloaded,
+            505 => IcapStatus::VersionNotSupported,
+            code => IcapStatus::Other(code),
+        }
+    }
+}
+
+impl From<IcapStatus> for u16 {
+    #[inline]
+    fn from(status: IcapStatus) -> u16 {
+        status.as_u16()
+    }
+}
+
+impl fmt::Display for IcapStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.canonical_reason() {
+            Some(reason) => write!(f, "{} {}", self.as_u16(), reason),
+            None => write!(f, "{}", self.as_u16()),
+        }
+    }
+}
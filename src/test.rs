@@ -1,9 +1,30 @@
 use super::*;
 
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod test {
     extern crate httparse;
+    #[cfg(feature = "alloc")]
+    use alloc::{format, vec};
+    #[cfg(feature = "alloc")]
+    use alloc::string::{String, ToString};
+    #[cfg(feature = "alloc")]
+    use alloc::vec::Vec;
     use super::{Request, Response, Status, EMPTY_HEADER, shrink, parse_chunk_size, SectionType};
+    use super::{EncapsulatedHeader, EncapsulationSection, Error, ParseMode, ParserConfig};
+    #[cfg(feature = "alloc")]
+    use super::{Connection, Event};
+    use super::{ISTag, OptionsResponse};
+    #[cfg(feature = "alloc")]
+    use super::{TokenList, Transfer};
+    use super::IcapMethod;
+    use super::IcapUri;
+    #[cfg(feature = "alloc")]
+    use super::Scheme;
+    use super::IcapStatus;
+    use super::ParseError;
+    use super::{EncodeError, Header, RequestBuilder, ResponseBuilder};
+    use super::{Chunk, ChunkedDecoder, ChunkExtension, ChunkExtensions, parse_chunk_head};
 
     const NUM_OF_HEADERS: usize = 4;
 
@@ -20,11 +41,12 @@ mod test {
     }
 
     macro_rules! req {
-        ($name:ident, $buf:expr, |$arg:ident| $body:expr) => (
-            req! {$name, $buf, Ok(Status::Complete($buf.len())), |$arg| $body }
+        ($(#[$attr:meta])* $name:ident, $buf:expr, |$arg:ident| $body:expr) => (
+            req! {$(#[$attr])* $name, $buf, Ok(Status::Complete($buf.len())), |$arg| $body }
         );
-        ($name:ident, $buf:expr, $len:expr, |$arg:ident| $body:expr) => (
+        ($(#[$attr:meta])* $name:ident, $buf:expr, $len:expr, |$arg:ident| $body:expr) => (
         #[test]
+        $(#[$attr])*
         fn $name() {
             let mut headers = [EMPTY_HEADER; NUM_OF_HEADERS];
             let mut req = Request::new(&mut headers[..]);
@@ -65,6 +87,7 @@ mod test {
     }
 
     req! {
+        #[cfg(feature = "alloc")]
         test_reqmod_basic,
         b"REQMOD icap://icap-server.net/server?arg=87 ICAP/1.0\r
 Host: icap-server.net\r
@@ -80,12 +103,12 @@ If-None-Match: \"xyzzy\", \"r2d2xxxx\"\r
 ",
        |req| {
            assert_eq!(req.method.unwrap(), "REQMOD");
-           let encapsulated = req.encapsulated_sections.unwrap();
+           let encapsulated = req.encapsulated_sections().unwrap();
            assert_eq!(encapsulated.len(), 2);
            let mut headers = [httparse::EMPTY_HEADER; 16];
            let mut req = httparse::Request::new(&mut headers);
            let http_request = encapsulated.get(&SectionType::RequestHeader).unwrap();
-           assert_eq!(req.parse(http_request).unwrap().is_complete(), true);
+           assert!(req.parse(http_request).unwrap().is_complete());
        }
     }
 
@@ -98,6 +121,7 @@ If-None-Match: \"xyzzy\", \"r2d2xxxx\"\r
     }
 
     req! {
+        #[cfg(feature = "alloc")]
         test_full_respmod,
         b"RESPMOD icap://icap.example.org/satisf ICAP/1.0\r
 Host: icap.example.org\r
@@ -123,7 +147,7 @@ This is data that was returned by an origin server.\r
         |req| {
             use SectionType::RequestHeader;
             assert_eq!(req.method.unwrap(), "RESPMOD");
-            let sections = req.encapsulated_sections.unwrap();
+            let sections = req.encapsulated_sections().unwrap();
             assert_eq!(sections[&RequestHeader], b"GET /origin-resource HTTP/1.1\r
 Host: www.origin-server.com\r
 Accept: text/html, text/plain, image/gif\r
@@ -135,6 +159,227 @@ Accept-Encoding: gzip, compress\r
 
 
 
+    req! {
+        test_respmod_borrowed_sections,
+        b"RESPMOD icap://icap.example.org/satisf ICAP/1.0\r
+Encapsulated: req-hdr=0, res-hdr=33, res-body=52\r
+\r
+GET /origin-resource HTTP/1.1\r
+\r
+HTTP/1.1 200 OK\r
+\r
+4\r
+Rust\r
+0\r
+\r
+",
+        |req| {
+            use SectionType::{RequestHeader, ResponseHeader, ResponseBody};
+            let sections = req.sections.unwrap();
+            assert_eq!(sections.len(), 3);
+            let names = [(RequestHeader, 0), (ResponseHeader, 33), (ResponseBody, 52)];
+            assert!(sections.iter().map(|s| (s.name, s.offset)).eq(names.iter().cloned()));
+            assert_eq!(sections.get(RequestHeader), Some(&b"GET /origin-resource HTTP/1.1\r\n\r\n"[..]));
+            assert_eq!(sections.get(ResponseHeader), Some(&b"HTTP/1.1 200 OK\r\n\r\n"[..]));
+            assert_eq!(sections.get(ResponseBody), Some(&b"4\r\nRust\r\n0\r\n\r\n"[..]));
+            assert_eq!(sections.get(SectionType::NullBody), None);
+        }
+    }
+
+    req! {
+        test_request_preview,
+        b"RESPMOD / ICAP/1.0\r\nPreview: 1024\r\nEncapsulated: null-body=0\r\n\r\n",
+        |req| {
+            assert_eq!(req.preview(), Ok(Some(1024)));
+        }
+    }
+
+    req! {
+        test_request_preview_invalid,
+        b"RESPMOD / ICAP/1.0\r\npreview: 1k\r\nEncapsulated: null-body=0\r\n\r\n",
+        |req| {
+            assert_eq!(req.preview(), Err(::Error::HeaderValue));
+        }
+    }
+
+    req! {
+        test_request_preview_missing,
+        b"RESPMOD / ICAP/1.0\r\nEncapsulated: null-body=0\r\n\r\n",
+        |req| {
+            assert_eq!(req.preview(), Ok(None));
+        }
+    }
+
+    req! {
+        test_request_encapsulated_req_body,
+        b"REQMOD / ICAP/1.0\r\nEncapsulated: req-hdr=0, req-body=18\r\n\r\nGET / HTTP/1.1\r\n\r\n0\r\n\r\n",
+        |req| {
+            let sections = req.sections.unwrap();
+            assert_eq!(sections.get(SectionType::RequestBody), Some(&b"0\r\n\r\n"[..]));
+            assert_eq!(sections.get(SectionType::ResponseBody), None);
+        }
+    }
+
+    req! {
+        test_request_encapsulated_invalid,
+        b"REQMOD / ICAP/1.0\r\nEncapsulated: req-body=0, res-body=5\r\n\r\n0\r\n\r\n",
+        Err(::Error::Encapsulated),
+        |_req| {}
+    }
+
+    req! {
+        test_request_reqmod_res_hdr_not_allowed,
+        b"REQMOD / ICAP/1.0\r\nEncapsulated: res-hdr=0, null-body=19\r\n\r\nHTTP/1.1 200 OK\r\n\r\n",
+        Err(Error::SectionNotAllowed),
+        |_req| {}
+    }
+
+    req! {
+        test_request_respmod_req_body_not_allowed,
+        b"RESPMOD / ICAP/1.0\r\nEncapsulated: req-body=0\r\n\r\n0\r\n\r\n",
+        Err(Error::SectionNotAllowed),
+        |_req| {}
+    }
+
+    req! {
+        test_request_options_req_hdr_not_allowed,
+        b"OPTIONS / ICAP/1.0\r\nEncapsulated: req-hdr=0, null-body=18\r\n\r\nGET / HTTP/1.1\r\n\r\n",
+        Err(Error::SectionNotAllowed),
+        |_req| {}
+    }
+
+    req! {
+        test_request_options_opt_body,
+        b"OPTIONS / ICAP/1.0\r\nEncapsulated: opt-body=0\r\n\r\n0\r\n\r\n",
+        |req| {
+            assert!(req.sections.unwrap().get(SectionType::OptionsBody).is_some());
+        }
+    }
+
+    req! {
+        test_request_extension_method_any_sections,
+        b"LOGMOD / ICAP/1.0\r\nEncapsulated: res-hdr=0, req-body=19\r\n\r\nHTTP/1.1 200 OK\r\n\r\n0\r\n\r\n",
+        |req| {
+            assert_eq!(req.sections.unwrap().len(), 2);
+        }
+    }
+
+    #[test]
+    fn test_request_allow_any_sections() {
+        let buf = b"REQMOD / ICAP/1.0\r\nEncapsulated: res-hdr=0, null-body=19\r\n\r\nHTTP/1.1 200 OK\r\n\r\n";
+        let mut headers = [EMPTY_HEADER; NUM_OF_HEADERS];
+        let mut req = Request::new(&mut headers);
+        let mut config = ParserConfig::default();
+        config.allow_any_sections(true);
+        assert_eq!(config.parse_request(&mut req, buf), Ok(Status::Complete(buf.len())));
+        assert!(req.sections.unwrap().get(SectionType::ResponseHeader).is_some());
+
+        let mut req = Request::new(&mut headers);
+        let mut config = ParserConfig::default();
+        config.mode(ParseMode::Lenient);
+        assert_eq!(config.parse_request(&mut req, buf), Ok(Status::Complete(buf.len())));
+        assert!(req.sections.unwrap().get(SectionType::ResponseHeader).is_some());
+
+        let mut req = Request::new(&mut headers);
+        config.mode(ParseMode::Strict);
+        assert_eq!(config.parse_request(&mut req, buf), Err(Error::SectionNotAllowed));
+    }
+
+    #[test]
+    fn test_request_reuse_resets_sections() {
+        let mut headers = [EMPTY_HEADER; NUM_OF_HEADERS];
+        let mut req = Request::new(&mut headers);
+        let buf = b"REQMOD / ICAP/1.0\r\nEncapsulated: req-body=0\r\n\r\n0\r\n\r\n";
+        assert_eq!(req.parse(buf), Ok(Status::Complete(buf.len())));
+        assert!(req.sections.is_some());
+        assert_eq!(req.parse(b"OPTIONS / ICAP/1.0\r\n\r\n"), Ok(Status::Complete(22)));
+        assert!(req.sections.is_none());
+    }
+
+    #[test]
+    fn test_response_reuse_resets_sections() {
+        let mut headers = [EMPTY_HEADER; NUM_OF_HEADERS];
+        let mut res = Response::new(&mut headers);
+        let buf = b"ICAP/1.0 200 OK\r\nEncapsulated: res-body=0\r\n\r\n0\r\n\r\n";
+        assert_eq!(res.parse(buf), Ok(Status::Complete(buf.len())));
+        assert!(res.sections.is_some());
+        assert_eq!(res.parse(b"ICAP/1.0 200 OK\r\nEncapsulated: res-body=0\r\n"), Ok(Status::Partial));
+        assert!(res.sections.is_none());
+    }
+
+    req! {
+        test_request_pipelined,
+        b"REQMOD / ICAP/1.0\r\nEncapsulated: req-body=0\r\n\r\n4\r\nRust\r\n0\r\n\r\nREQMOD / ICAP/1.0\r\n",
+        Ok(Status::Complete(61)),
+        |req| {
+            assert_eq!(req.encapsulated_offset(), Some(47));
+            let sections = req.sections.unwrap();
+            assert!(sections.is_body_complete());
+            assert_eq!(sections.get(SectionType::RequestBody), Some(&b"4\r\nRust\r\n0\r\n\r\n"[..]));
+        }
+    }
+
+    req! {
+        test_request_null_body_pipelined,
+        b"REQMOD / ICAP/1.0\r\nEncapsulated: req-hdr=0, null-body=18\r\n\r\nGET / HTTP/1.1\r\n\r\nOPTIONS",
+        Ok(Status::Complete(78)),
+        |req| {
+            let sections = req.sections.unwrap();
+            assert!(sections.is_body_complete());
+            assert_eq!(sections.get(SectionType::NullBody), Some(&b""[..]));
+        }
+    }
+
+    req! {
+        test_request_options_pipelined,
+        b"OPTIONS / ICAP/1.0\r\nHost: example.local\r\n\r\nOPTIONS / ICAP/1.0\r\n",
+        Ok(Status::Complete(43)),
+        |req| {
+            assert_eq!(req.encapsulated_offset(), Some(43));
+            assert!(req.sections.is_none());
+        }
+    }
+
+    req! {
+        test_request_body_incomplete,
+        b"REQMOD / ICAP/1.0\r\nEncapsulated: req-body=0\r\n\r\n4\r\nRu",
+        |req| {
+            let sections = req.sections.unwrap();
+            assert!(!sections.is_body_complete());
+            assert_eq!(sections.get(SectionType::RequestBody), Some(&b"4\r\nRu"[..]));
+        }
+    }
+
+    req! {
+        test_request_body_invalid_chunk,
+        b"REQMOD / ICAP/1.0\r\nEncapsulated: req-body=0\r\n\r\nzz\r\n",
+        Err(Error::Chunk),
+        |_req| {}
+    }
+
+    req! {
+        test_request_encapsulated_partial,
+        b"RESPMOD / ICAP/1.0\r\nEncapsulated: req-hdr=0, res-hdr=33, res-body=52\r\n\r\nGET /origin-resource HTTP/1.1\r\n\r\nHTTP/1.1 200",
+        Ok(Status::Partial),
+        |req| {
+            assert!(req.sections.is_none());
+        }
+    }
+
+    req! {
+        test_request_null_body_partial,
+        b"REQMOD / ICAP/1.0\r\nEncapsulated: req-hdr=0, null-body=170\r\n\r\nGET / HTTP/1.1\r\n\r\n",
+        Ok(Status::Partial),
+        |_req| {}
+    }
+
+    req! {
+        test_request_encapsulated_offset_mismatch,
+        b"REQMOD / ICAP/1.0\r\nEncapsulated: req-hdr=0, req-body=10\r\n\r\nGET / HTTP/1.1\r\n\r\n0\r\n\r\n",
+        Err(Error::SectionOffset),
+        |_req| {}
+    }
+
     req! {
         test_request_headers_max,
         b"RESPMOD / ICAP/1.0\r\nA: A\r\nB: B\r\nC: C\r\nEncapsulated:null-body=0\r\n\r\n",
@@ -189,11 +434,12 @@ Accept-Encoding: gzip, compress\r
     }
 
     macro_rules! res {
-        ($name:ident, $buf:expr, |$arg:ident| $body:expr) => (
-            res! {$name, $buf, Ok(Status::Complete($buf.len())), |$arg| $body }
+        ($(#[$attr:meta])* $name:ident, $buf:expr, |$arg:ident| $body:expr) => (
+            res! {$(#[$attr])* $name, $buf, Ok(Status::Complete($buf.len())), |$arg| $body }
         );
-        ($name:ident, $buf:expr, $len:expr, |$arg:ident| $body:expr) => (
+        ($(#[$attr:meta])* $name:ident, $buf:expr, $len:expr, |$arg:ident| $body:expr) => (
         #[test]
+        $(#[$attr])*
         fn $name() {
             let mut headers = [EMPTY_HEADER; NUM_OF_HEADERS];
             let mut res = Response::new(&mut headers[..]);
@@ -254,7 +500,7 @@ Accept-Encoding: gzip, compress\r
         }
     }
 
-    static RESPONSE_REASON_WITH_OBS_TEXT_BYTE: &'static [u8] = b"ICAP/1.0 200 X\xFFZ\r\n\r\n";
+    static RESPONSE_REASON_WITH_OBS_TEXT_BYTE: &[u8] = b"ICAP/1.0 200 X\xFFZ\r\n\r\n";
     res! {
         test_response_reason_with_obsolete_text_byte,
         RESPONSE_REASON_WITH_OBS_TEXT_BYTE,
@@ -289,6 +535,124 @@ Accept-Encoding: gzip, compress\r
         |_res| {}
     }
 
+    res! {
+        #[cfg(feature = "alloc")]
+        test_response_respmod_encapsulated,
+        b"ICAP/1.0 200 OK\r
+ISTag: \"W3E4R7U9-L2E4-2\"\r
+Encapsulated: res-hdr=0, res-body=64\r
+\r
+HTTP/1.1 200 OK\r
+Content-Type: text/html\r
+Content-Length: 13\r
+\r
+d\r
+Hello, World!\r
+0\r
+\r
+",
+        |res| {
+            use SectionType::{ResponseHeader, ResponseBody};
+            assert_eq!(res.code.unwrap(), 200);
+            let sections = res.encapsulated_sections().unwrap();
+            assert_eq!(sections.len(), 2);
+            assert_eq!(sections[&ResponseHeader], b"HTTP/1.1 200 OK\r
+Content-Type: text/html\r
+Content-Length: 13\r
+\r
+".to_vec());
+            assert_eq!(sections[&ResponseBody], b"d\r\nHello, World!\r\n0\r\n\r\n".to_vec());
+        }
+    }
+
+    res! {
+        #[cfg(feature = "alloc")]
+        test_response_options_body,
+        b"ICAP/1.0 200 OK\r\nMethods: RESPMOD\r\nEncapsulated: opt-body=0\r\n\r\n4\r\nRust\r\n0\r\n\r\n",
+        |res| {
+            let sections = res.encapsulated_sections().unwrap();
+            assert_eq!(sections[&SectionType::OptionsBody], b"4\r\nRust\r\n0\r\n\r\n".to_vec());
+        }
+    }
+
+    res! {
+        test_response_encapsulated_partial,
+        b"ICAP/1.0 200 OK\r\nEncapsulated: res-hdr=0, res-body=64\r\n\r\nHTTP/1.1 200 OK\r\n",
+        Ok(Status::Partial),
+        |res| {
+            assert_eq!(res.code.unwrap(), 200);
+            assert!(res.sections.is_none());
+        }
+    }
+
+    res! {
+        #[cfg(feature = "alloc")]
+        test_response_without_encapsulated,
+        b"ICAP/1.0 100 Continue\r\n\r\n",
+        |res| {
+            assert_eq!(res.code.unwrap(), 100);
+            assert!(res.encapsulated_sections().is_none());
+        }
+    }
+
+    #[test]
+    fn test_encapsulated_header() {
+        use SectionType::*;
+        let header = EncapsulatedHeader::parse(b"req-hdr=0, res-hdr=137, res-body=296").unwrap();
+        assert_eq!(header.as_slice(), &[
+            EncapsulationSection::new(RequestHeader, 0),
+            EncapsulationSection::new(ResponseHeader, 137),
+            EncapsulationSection::new(ResponseBody, 296),
+        ]);
+        let header = EncapsulatedHeader::parse(b"req-hdr=0,req-body=147 ").unwrap();
+        assert_eq!(header.get(RequestBody), Some(147));
+        assert_eq!(header.get(ResponseBody), None);
+        assert_eq!(header.body(), EncapsulationSection::new(RequestBody, 147));
+        assert_eq!(EncapsulatedHeader::parse(b"null-body=0").unwrap().len(), 1);
+        assert_eq!(EncapsulatedHeader::parse(b"opt-body=0").unwrap().body().name(), OptionsBody);
+    }
+
+    #[test]
+    fn test_encapsulated_header_invalid() {
+        let invalid: &[&[u8]] = &[
+            b"",
+            b"garbage",
+            b"req-hdr=0",
+            b"req-hdr=, null-body=0",
+            b"req-hdr=0 null-body=10",
+            b"req-hdr=0,, null-body=10",
+            b"req-hdr=0, null-body=10,",
+            b"req-hdr = 0, null-body=10",
+            b"req-hdr=0, req-hdr=10, null-body=20",
+            b"res-hdr=0, req-hdr=10, null-body=20",
+            b"req-hdr=10, res-hdr=0, null-body=20",
+            b"req-hdr=0, res-hdr=0, null-body=20",
+            b"req-body=0, res-body=10",
+            b"null-body=0, res-hdr=10",
+            b"foo-hdr=0, null-body=10",
+            b"req-hdr=0, null-body=99999999999999999999999",
+            b"req-hdr=5, null-body=23",
+            b"null-body=7",
+            b"res-body=1",
+        ];
+        for value in invalid {
+            assert_eq!(EncapsulatedHeader::parse(value), Err(Error::Encapsulated), "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn test_encapsulated_first_offset() {
+        let buf = b"REQMOD / ICAP/1.0\r\nEncapsulated: req-hdr=5, null-body=23\r\n\r\nJUNK!GET / HTTP/1.1\r\n\r\n";
+        let mut headers = [EMPTY_HEADER; NUM_OF_HEADERS];
+        let mut req = Request::new(&mut headers);
+        assert_eq!(req.parse(buf), Err(Error::Encapsulated));
+
+        let buf = b"ICAP/1.0 204 No Content\r\nEncapsulated: null-body=7\r\n\r\n";
+        let mut headers = [EMPTY_HEADER; NUM_OF_HEADERS];
+        let mut res = Response::new(&mut headers);
+        assert_eq!(res.parse(buf), Err(Error::Encapsulated));
+    }
+
     #[test]
     fn test_chunk_size() {
         assert_eq!(parse_chunk_size(b"0\r\n"), Ok(Status::Complete((3, 0))));
@@ -301,14 +665,1411 @@ Accept-Encoding: gzip, compress\r
         assert_eq!(parse_chunk_size(b"567f8a\rfoo"), Err(::InvalidChunkSize));
         assert_eq!(parse_chunk_size(b"567f8a\rfoo"), Err(::InvalidChunkSize));
         assert_eq!(parse_chunk_size(b"567xf8a\r\n"), Err(::InvalidChunkSize));
-        assert_eq!(parse_chunk_size(b"ffffffffffffffff\r\n"), Ok(Status::Complete((18, ::core::u64::MAX))));
+        assert_eq!(parse_chunk_size(b"\r\n"), Err(::InvalidChunkSize));
+        assert_eq!(parse_chunk_size(b";ext\r\n"), Err(::InvalidChunkSize));
+        assert_eq!(parse_chunk_size(b" 1\r\n"), Err(::InvalidChunkSize));
+        assert_eq!(parse_chunk_size(b"ffffffffffffffff\r\n"), Ok(Status::Complete((18, u64::MAX))));
         assert_eq!(parse_chunk_size(b"1ffffffffffffffff\r\n"), Err(::InvalidChunkSize));
         assert_eq!(parse_chunk_size(b"Affffffffffffffff\r\n"), Err(::InvalidChunkSize));
         assert_eq!(parse_chunk_size(b"fffffffffffffffff\r\n"), Err(::InvalidChunkSize));
     }
 
+    #[test]
+    fn test_chunk_extensions() {
+        let (pos, size, ext) = parse_chunk_head(b"0\r\n\r\n").unwrap().unwrap();
+        assert_eq!((pos, size), (3, 0));
+        assert!(ext.is_empty());
+        assert!(!ext.ieof());
+
+        let (pos, size, ext) = parse_chunk_head(b"0; ieof\r\n\r\n").unwrap().unwrap();
+        assert_eq!((pos, size), (9, 0));
+        assert!(ext.ieof());
+
+        let (_, size, ext) = parse_chunk_head(b"1f;a=1; b = \"x;y\" ;IEOF\r\n").unwrap().unwrap();
+        assert_eq!(size, 31);
+        assert!(ext.ieof());
+        let exts = [
+            ChunkExtension { name: b"a", value: Some(b"1") },
+            ChunkExtension { name: b"b", value: Some(b"x;y") },
+            ChunkExtension { name: b"IEOF", value: None },
+        ];
+        assert!(ext.iter().eq(exts.iter().cloned()));
+        assert_eq!(ext.get("B").unwrap().value, Some(&b"x;y"[..]));
+    }
+
+    #[test]
+    fn test_chunked_decoder_ieof() {
+        let mut decoder = ChunkedDecoder::new();
+        match decoder.decode(b"0; ieof\r\n\r\n") {
+            Ok(Status::Complete((11, Chunk::Last { extensions }))) => assert!(extensions.ieof()),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_chunked_decoder() {
+        let buf = b"4;name=value\r\nRust\r\n0; ieof\r\n\r\n";
+        let mut decoder = ChunkedDecoder::new();
+        assert_eq!(decoder.decode(buf), Ok(Status::Complete((14, Chunk::Head { size: 4, extensions: ChunkExtensions::new(b"name=value") }))));
+        assert_eq!(decoder.decode(&buf[14..]), Ok(Status::Complete((4, Chunk::Data(b"Rust")))));
+        assert!(!decoder.is_done());
+        assert_eq!(decoder.decode(&buf[18..]), Ok(Status::Complete((13, Chunk::Last { extensions: ChunkExtensions::new(b" ieof") }))));
+        assert!(decoder.is_done());
+        assert_eq!(decoder.decode(&buf[31..]), Ok(Status::Complete((0, Chunk::Last { extensions: ChunkExtensions::new(b"") }))));
+    }
+
+    #[test]
+    fn test_chunked_decoder_partial() {
+        let mut decoder = ChunkedDecoder::new();
+        assert_eq!(decoder.decode(b"a\r"), Ok(Status::Partial));
+        assert_eq!(decoder.decode(b"a\r\nabc"), Ok(Status::Complete((3, Chunk::Head { size: 10, extensions: ChunkExtensions::new(b"") }))));
+        assert_eq!(decoder.decode(b""), Ok(Status::Partial));
+        assert_eq!(decoder.decode(b"abc"), Ok(Status::Complete((3, Chunk::Data(b"abc")))));
+        assert_eq!(decoder.decode(b"defghij\r\n1"), Ok(Status::Complete((7, Chunk::Data(b"defghij")))));
+        assert_eq!(decoder.decode(b"\r\n1"), Ok(Status::Partial));
+        assert_eq!(decoder.decode(b"\r\n0\r\n"), Ok(Status::Partial));
+        assert_eq!(decoder.decode(b"\r\n0\r\nX-Trailer: 1\r\n\r\n"), Ok(Status::Complete((21, Chunk::Last { extensions: ChunkExtensions::new(b"") }))));
+        assert!(decoder.is_done());
+    }
+
+    #[test]
+    fn test_chunked_decoder_preview() {
+        let mut decoder = ChunkedDecoder::preview();
+        assert_eq!(decoder.decode(b"2\r\nab\r\n0\r\n\r\n"), Ok(Status::Complete((3, Chunk::Head { size: 2, extensions: ChunkExtensions::new(b"") }))));
+        assert_eq!(decoder.decode(b"ab\r\n0\r\n\r\n"), Ok(Status::Complete((2, Chunk::Data(b"ab")))));
+        assert_eq!(decoder.decode(b"\r\n0\r\n\r\n"), Ok(Status::Complete((7, Chunk::PreviewEnd { extensions: ChunkExtensions::new(b"") }))));
+        assert!(decoder.is_preview_end());
+        assert!(!decoder.is_done());
+        assert_eq!(decoder.decode(b"1\r\nc"), Ok(Status::Partial));
+
+        decoder.resume();
+        assert!(!decoder.is_preview_end());
+        assert_eq!(decoder.decode(b"1\r\nc\r\n0\r\n\r\n"), Ok(Status::Complete((3, Chunk::Head { size: 1, extensions: ChunkExtensions::new(b"") }))));
+        assert_eq!(decoder.decode(b"c\r\n0\r\n\r\n"), Ok(Status::Complete((1, Chunk::Data(b"c")))));
+        assert_eq!(decoder.decode(b"\r\n0\r\n\r\n"), Ok(Status::Complete((7, Chunk::Last { extensions: ChunkExtensions::new(b"") }))));
+        assert!(decoder.is_done());
+    }
+
+    #[test]
+    fn test_chunked_decoder_preview_ieof() {
+        let mut decoder = ChunkedDecoder::preview();
+        assert_eq!(decoder.decode(b"0; ieof\r\n\r\n"), Ok(Status::Complete((11, Chunk::Last { extensions: ChunkExtensions::new(b" ieof") }))));
+        assert!(decoder.is_done());
+        assert!(!decoder.is_preview_end());
+    }
+
+    #[test]
+    fn test_chunked_decoder_invalid() {
+        let mut decoder = ChunkedDecoder::new();
+        assert_eq!(decoder.decode(b"2\r\nab"), Ok(Status::Complete((3, Chunk::Head { size: 2, extensions: ChunkExtensions::new(b"") }))));
+        assert_eq!(decoder.decode(b"ab"), Ok(Status::Complete((2, Chunk::Data(b"ab")))));
+        assert_eq!(decoder.decode(b"xx0\r\n\r\n"), Err(Error::Chunk));
+        assert_eq!(ChunkedDecoder::new().decode(b"zz\r\n"), Err(Error::Chunk));
+        // a missing size is not the last chunk
+        assert_eq!(ChunkedDecoder::new().decode(b"\r\n\r\n"), Err(Error::Chunk));
+
+        let buf = b"REQMOD / ICAP/1.0\r\nEncapsulated: req-body=0\r\n\r\n\r\n\r\nXXXX";
+        let mut headers = [EMPTY_HEADER; NUM_OF_HEADERS];
+        let mut req = Request::new(&mut headers);
+        assert_eq!(req.parse(buf), Err(Error::Chunk));
+    }
+
+    #[test]
+    fn test_request_builder_respmod() {
+        let headers = [
+            Header { name: "Host", value: b"icap.example.org" },
+            Header { name: "Encapsulated", value: b"ignored" },
+        ];
+        let builder = RequestBuilder::new("RESPMOD", "icap://icap.example.org/satisf")
+            .headers(&headers)
+            .req_hdr(b"GET /origin-resource HTTP/1.1\r\n\r\n")
+            .res_hdr(b"HTTP/1.1 200 OK\r\n\r\n")
+            .res_body(b"This is data that was returned by an origin server.");
+        let mut buf = [0; 512];
+        let len = builder.encode(&mut buf).unwrap();
+        assert_eq!(len, builder.encoded_len());
+        assert_eq!(&buf[..len], &b"RESPMOD icap://icap.example.org/satisf ICAP/1.0\r
+Host: icap.example.org\r
+Encapsulated: req-hdr=0, res-hdr=33, res-body=52\r
+\r
+GET /origin-resource HTTP/1.1\r
+\r
+HTTP/1.1 200 OK\r
+\r
+33\r
+This is data that was returned by an origin server.\r
+0\r
+\r
+"[..]);
+
+        let mut headers = [EMPTY_HEADER; NUM_OF_HEADERS];
+        let mut req = Request::new(&mut headers);
+        assert_eq!(req.parse(&buf[..len]), Ok(Status::Complete(len)));
+        let sections = req.sections.unwrap();
+        assert_eq!(sections.get(SectionType::ResponseHeader), Some(&b"HTTP/1.1 200 OK\r\n\r\n"[..]));
+    }
+
+    #[test]
+    fn test_request_builder_null_body() {
+        let builder = RequestBuilder::new("OPTIONS", "icap://icap.example.org/satisf");
+        let mut buf = [0; 128];
+        let len = builder.encode(&mut buf).unwrap();
+        assert_eq!(&buf[..len], &b"OPTIONS icap://icap.example.org/satisf ICAP/1.0\r\nEncapsulated: null-body=0\r\n\r\n"[..]);
+
+        let builder = RequestBuilder::new("REQMOD", "/").req_hdr(b"GET / HTTP/1.1\r\n\r\n");
+        let len = builder.encode(&mut buf).unwrap();
+        assert_eq!(&buf[..len], &b"REQMOD / ICAP/1.0\r\nEncapsulated: req-hdr=0, null-body=18\r\n\r\nGET / HTTP/1.1\r\n\r\n"[..]);
+
+        let builder = RequestBuilder::new("REQMOD", "/").req_body(b"");
+        let len = builder.encode(&mut buf).unwrap();
+        assert_eq!(&buf[..len], &b"REQMOD / ICAP/1.0\r\nEncapsulated: req-body=0\r\n\r\n0\r\n\r\n"[..]);
+    }
+
+    #[test]
+    fn test_request_builder_buffer_too_small() {
+        let builder = RequestBuilder::new("REQMOD", "/").req_body(b"Rust");
+        let mut buf = [0; 16];
+        assert_eq!(builder.encode(&mut buf), Err(EncodeError::BufferTooSmall));
+    }
+
+    #[test]
+    fn test_request_builder_invalid() {
+        use super::Error;
+        let mut buf = [0; 128];
+        let headers = [Header { name: "X-Note", value: b"a\r\nEvil: 1" }];
+        let builder = RequestBuilder::new("REQMOD", "/").headers(&headers);
+        assert_eq!(builder.encode(&mut buf), Err(EncodeError::Invalid(Error::HeaderValue)));
+        let headers = [Header { name: "X-Note\r\nEvil", value: b"1" }];
+        let builder = RequestBuilder::new("REQMOD", "/").headers(&headers);
+        assert_eq!(builder.encode(&mut buf), Err(EncodeError::Invalid(Error::HeaderName)));
+        let builder = RequestBuilder::new("REQMOD /", "/");
+        assert_eq!(builder.encode(&mut buf), Err(EncodeError::Invalid(Error::Token)));
+        let builder = RequestBuilder::new("", "/");
+        assert_eq!(builder.encode(&mut buf), Err(EncodeError::Invalid(Error::Token)));
+        let builder = RequestBuilder::new("REQMOD", "/ ICAP/1.0\r\nEvil: 1");
+        assert_eq!(builder.encode(&mut buf), Err(EncodeError::Invalid(Error::Uri)));
+        let builder = RequestBuilder::new("REQMOD", "/").req_hdr(b"GET / HTTP/1.1\r\n").req_body(b"x");
+        assert_eq!(builder.encode(&mut buf), Err(EncodeError::Invalid(Error::SectionOffset)));
+        let builder = RequestBuilder::new("RESPMOD", "/").req_hdr(b"GET / HTTP/1.1\r\n\r\n").res_hdr(b"HTTP/1.1 200 OK\r\n");
+        assert_eq!(builder.encode(&mut buf), Err(EncodeError::Invalid(Error::SectionOffset)));
+        assert_eq!(buf[0], 0);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_request_builder_write_to_invalid() {
+        let headers = [Header { name: "X-Note", value: b"a\r\nEvil: 1" }];
+        let builder = RequestBuilder::new("REQMOD", "/").headers(&headers);
+        let mut out = Vec::new();
+        let err = builder.write_to(&mut out).unwrap_err();
+        assert_eq!(err.kind(), ::std::io::ErrorKind::InvalidInput);
+        assert!(out.is_empty());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_request_builder_write_to() {
+        let builder = RequestBuilder::new("REQMOD", "/").req_body(b"Rust");
+        let mut out = Vec::new();
+        assert_eq!(builder.write_to(&mut out).unwrap(), builder.encoded_len());
+        assert_eq!(out, b"REQMOD / ICAP/1.0\r\nEncapsulated: req-body=0\r\n\r\n4\r\nRust\r\n0\r\n\r\n".to_vec());
+    }
+
+    #[test]
+    fn test_response_builder() {
+        let headers = [Header { name: "Service", value: b"FOO Tech Server 1.0" }];
+        let builder = ResponseBuilder::new(200, "OK")
+            .istag(b"\"W3E4R7U9-L2E4-2\"")
+            .headers(&headers)
+            .res_hdr(b"HTTP/1.1 200 OK\r\nContent-Length: 13\r\n\r\n")
+            .res_body(b"Hello, World!");
+        let mut buf = [0; 256];
+        let len = builder.encode(&mut buf).unwrap();
+        assert_eq!(len, builder.encoded_len());
+        assert_eq!(&buf[..len], &b"ICAP/1.0 200 OK\r
+ISTag: \"W3E4R7U9-L2E4-2\"\r
+Service: FOO Tech Server 1.0\r
+Encapsulated: res-hdr=0, res-body=39\r
+\r
+HTTP/1.1 200 OK\r
+Content-Length: 13\r
+\r
+d\r
+Hello, World!\r
+0\r
+\r
+"[..]);
+
+        let mut headers = [EMPTY_HEADER; NUM_OF_HEADERS];
+        let mut res = Response::new(&mut headers);
+        assert_eq!(res.parse(&buf[..len]), Ok(Status::Complete(len)));
+        let sections = res.sections.unwrap();
+        assert_eq!(sections.get(SectionType::ResponseBody), Some(&b"d\r\nHello, World!\r\n0\r\n\r\n"[..]));
+    }
+
+    #[test]
+    fn test_response_builder_no_body() {
+        let mut buf = [0; 128];
+        let len = ResponseBuilder::new(100, "Continue").encode(&mut buf).unwrap();
+        assert_eq!(&buf[..len], &b"ICAP/1.0 100 Continue\r\n\r\n"[..]);
+
+        let len = ResponseBuilder::new(204, "No Content").istag(b"\"1\"").encode(&mut buf).unwrap();
+        assert_eq!(&buf[..len], &b"ICAP/1.0 204 No Content\r\nISTag: \"1\"\r\nEncapsulated: null-body=0\r\n\r\n"[..]);
+
+        let len = ResponseBuilder::new(200, "OK").opt_body(b"").encode(&mut buf).unwrap();
+        assert_eq!(&buf[..len], &b"ICAP/1.0 200 OK\r\nEncapsulated: opt-body=0\r\n\r\n0\r\n\r\n"[..]);
+
+        let len = ResponseBuilder::new(500, "Server Error").encode(&mut buf).unwrap();
+        assert_eq!(&buf[..len], &b"ICAP/1.0 500 Server Error\r\nEncapsulated: null-body=0\r\n\r\n"[..]);
+    }
+
+    #[test]
+    fn test_response_builder_invalid() {
+        use super::Error;
+        let mut buf = [0; 128];
+        let res = ResponseBuilder::new(99, "Weird");
+        assert_eq!(res.encode(&mut buf), Err(EncodeError::Invalid(Error::Status)));
+        let res = ResponseBuilder::new(1000, "Weird");
+        assert_eq!(res.encode(&mut buf), Err(EncodeError::Invalid(Error::Status)));
+        let res = ResponseBuilder::new(200, "OK\r\nEvil: 1");
+        assert_eq!(res.encode(&mut buf), Err(EncodeError::Invalid(Error::Status)));
+        let res = ResponseBuilder::new(200, "Okay\u{e9}");
+        assert_eq!(res.encode(&mut buf), Err(EncodeError::Invalid(Error::Status)));
+        let res = ResponseBuilder::new(200, "OK").istag(b"\"1\"\r\nEvil: 1");
+        assert_eq!(res.encode(&mut buf), Err(EncodeError::Invalid(Error::HeaderValue)));
+        let res = ResponseBuilder::new(200, "OK").res_hdr(b"HTTP/1.1 200 OK\r\n");
+        assert_eq!(res.encode(&mut buf), Err(EncodeError::Invalid(Error::SectionOffset)));
+        let headers = [Header { name: "X-Note", value: b"a\nEvil: 1" }];
+        let res = ResponseBuilder::new(200, "OK").headers(&headers);
+        assert_eq!(res.encode(&mut buf), Err(EncodeError::Invalid(Error::HeaderValue)));
+        assert_eq!(buf[0], 0);
+    }
+
+    #[test]
+    fn test_response_builder_istag_once() {
+        let headers = [
+            Header { name: "istag", value: b"\"old\"" },
+            Header { name: "Service", value: b"Rust" },
+        ];
+        let mut buf = [0; 128];
+        let len = ResponseBuilder::new(204, "No Content").headers(&headers).encode(&mut buf).unwrap();
+        assert_eq!(&buf[..len], &b"ICAP/1.0 204 No Content\r\nistag: \"old\"\r\nService: Rust\r\nEncapsulated: null-body=0\r\n\r\n"[..]);
+
+        let res = ResponseBuilder::new(204, "No Content").istag(b"\"new\"").headers(&headers);
+        let len = res.encode(&mut buf).unwrap();
+        assert_eq!(&buf[..len], &b"ICAP/1.0 204 No Content\r\nISTag: \"new\"\r\nService: Rust\r\nEncapsulated: null-body=0\r\n\r\n"[..]);
+    }
+
+    /// Polls a connection until the buffer is exhausted or more bytes are
+    /// needed, describing each event.
+    #[cfg(feature = "alloc")]
+    fn poll_all(conn: &mut Connection, mut buf: &[u8]) -> Vec<String> {
+        let mut events = Vec::new();
+        loop {
+            let mut headers = [EMPTY_HEADER; NUM_OF_HEADERS];
+            let (len, event) = match conn.poll(buf, &mut headers) {
+                Ok(Status::Complete(v)) => v,
+                Ok(Status::Partial) => return events,
+                Err(e) => {
+                    events.push(format!("error {:?}", e));
+                    return events;
+                },
+            };
+            buf = &buf[len..];
+            events.push(match event {
+                Event::Request(req) => format!("request {}", req.method.unwrap()),
+                Event::Response(res) => format!("response {}", res.code.unwrap()),
+                Event::Header(section) => format!("{} {:?}", section.name, String::from_utf8_lossy(section.value)),
+                Event::Body(data) => format!("body {:?}", String::from_utf8_lossy(data)),
+                Event::PreviewEnd => "preview end".to_string(),
+                Event::End => "end".to_string(),
+                Event::Closed => return events,
+            });
+        }
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_connection_server_pipelined() {
+        let mut conn = Connection::server();
+        let events = poll_all(&mut conn, b"RESPMOD icap://icap.example.org/satisf ICAP/1.0\r
+Encapsulated: req-hdr=0, res-hdr=33, res-body=52\r
+\r
+GET /origin-resource HTTP/1.1\r
+\r
+HTTP/1.1 200 OK\r
+\r
+4\r
+Rust\r
+3\r
+ace\r
+0\r
+\r
+OPTIONS icap://icap.example.org/satisf ICAP/1.0\r
+Connection: close\r
+\r
+");
+        assert_eq!(events, vec![
+            "request RESPMOD",
+            "req-hdr \"GET /origin-resource HTTP/1.1\\r\\n\\r\\n\"",
+            "res-hdr \"HTTP/1.1 200 OK\\r\\n\\r\\n\"",
+            "body \"Rust\"",
+            "body \"ace\"",
+            "end",
+            "request OPTIONS",
+            "end",
+        ]);
+        assert!(!conn.keep_alive());
+        assert!(conn.is_closed());
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_connection_server_partial() {
+        let mut conn = Connection::server();
+        let buf = b"REQMOD / ICAP/1.0\r\nEncapsulated: req-body=0\r\n\r\n4\r\nRu";
+        assert_eq!(poll_all(&mut conn, &buf[..20]), Vec::<String>::new());
+        assert_eq!(poll_all(&mut conn, buf), vec!["request REQMOD", "body \"Ru\""]);
+        assert_eq!(poll_all(&mut conn, b"st\r\n0\r"), vec!["body \"st\""]);
+        assert_eq!(poll_all(&mut conn, b"\r\n0\r\n\r\n"), vec!["end"]);
+        assert!(conn.keep_alive());
+        assert!(!conn.is_closed());
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_connection_server_preview() {
+        let mut conn = Connection::server();
+        let events = poll_all(&mut conn, b"RESPMOD / ICAP/1.0\r\nPreview: 4\r\nEncapsulated: res-body=0\r\n\r\n4\r\nRust\r\n0\r\n\r\n");
+        assert_eq!(events, vec!["request RESPMOD", "body \"Rust\"", "preview end"]);
+        assert!(conn.is_preview_end());
+        assert_eq!(poll_all(&mut conn, b"3\r\nace\r\n0\r\n\r\n"), Vec::<String>::new());
+
+        conn.resume_preview();
+        assert_eq!(poll_all(&mut conn, b"3\r\nace\r\n0\r\n\r\n"), vec!["body \"ace\"", "end"]);
+
+        let events = poll_all(&mut conn, b"RESPMOD / ICAP/1.0\r\nPreview: 4\r\nEncapsulated: res-body=0\r\n\r\n0\r\n\r\n");
+        assert_eq!(events, vec!["request RESPMOD", "preview end"]);
+        conn.end_preview();
+        let events = poll_all(&mut conn, b"RESPMOD / ICAP/1.0\r\nPreview: 4\r\nEncapsulated: res-body=0\r\n\r\n0; ieof\r\n\r\n");
+        assert_eq!(events, vec!["end", "request RESPMOD", "end"]);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_connection_client() {
+        let mut conn = Connection::client();
+        let events = poll_all(&mut conn, b"ICAP/1.0 100 Continue\r
+\r
+ICAP/1.0 200 OK\r
+Encapsulated: res-hdr=0, res-body=19\r
+\r
+HTTP/1.1 200 OK\r
+\r
+4\r
+Rust\r
+0\r
+\r
+ICAP/1.0 204 No Content\r
+Encapsulated: null-body=0\r
+\r
+");
+        assert_eq!(events, vec![
+            "response 100",
+            "end",
+            "response 200",
+            "res-hdr \"HTTP/1.1 200 OK\\r\\n\\r\\n\"",
+            "body \"Rust\"",
+            "end",
+            "response 204",
+            "end",
+        ]);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_connection_invalid_chunk() {
+        let mut conn = Connection::server();
+        let events = poll_all(&mut conn, b"REQMOD / ICAP/1.0\r\nEncapsulated: req-body=0\r\n\r\n4\r\nRust\r\nzz\r\n");
+        assert_eq!(events, vec!["request REQMOD", "body \"Rust\"", "error Chunk"]);
+    }
+
+    #[test]
+    fn test_request_skip_body() {
+        // the body is invalid past its first chunk, which is only found when
+        // it is scanned
+        let buf = b"REQMOD / ICAP/1.0\r\nEncapsulated: req-hdr=0, req-body=18\r\n\r\nGET / HTTP/1.1\r\n\r\n4\r\nRust\r\nzz\r\n";
+        let mut headers = [EMPTY_HEADER; NUM_OF_HEADERS];
+        let mut req = Request::new(&mut headers);
+        assert_eq!(req.parse(buf), Err(Error::Chunk));
+
+        let mut req = Request::new(&mut headers);
+        let mut config = ParserConfig::default();
+        config.skip_body(true);
+        assert_eq!(config.parse_request(&mut req, buf), Ok(Status::Complete(77)));
+        let sections = req.sections.unwrap();
+        assert_eq!(sections.get(SectionType::RequestHeader), Some(&b"GET / HTTP/1.1\r\n\r\n"[..]));
+        assert_eq!(sections.get(SectionType::RequestBody), Some(&b""[..]));
+        assert!(!sections.is_body_complete());
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_connection_with_config() {
+        let buf = b"REQMOD / ICAP/1.0\r\nEncapsulated: res-hdr=0, null-body=19\r\n\r\nHTTP/1.1 200 OK\r\n\r\n";
+        let mut conn = Connection::server();
+        assert_eq!(poll_all(&mut conn, buf), vec!["error SectionNotAllowed"]);
+
+        let mut config = ParserConfig::default();
+        config.allow_any_sections(true);
+        let mut conn = Connection::server().with_config(config);
+        assert_eq!(poll_all(&mut conn, buf), vec![
+            "request REQMOD",
+            "res-hdr \"HTTP/1.1 200 OK\\r\\n\\r\\n\"",
+            "end",
+        ]);
+
+        let mut config = ParserConfig::default();
+        config.mode(ParseMode::Strict);
+        let mut conn = Connection::client().with_config(config);
+        assert_eq!(poll_all(&mut conn, b"ICAP/1.0 204\r\nEncapsulated: null-body=0\r\n\r\n"), vec!["error Status"]);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_options_response() {
+        let buf = b"ICAP/1.0 200 OK\r
+Date: Mon, 10 Jan 2000  09:55:21 GMT\r
+Methods: RESPMOD, REQMOD\r
+Service: FOO Tech Server 1.0\r
+ISTag: \"W3E4R7U9-L2E4-2\"\r
+Encapsulated: opt-body=0\r
+Max-Connections: 1000\r
+Options-TTL: 7200\r
+Service-ID: foo\r
+Allow: 204, 206\r
+Preview: 2048\r
+Transfer-Complete: asp, bat, exe, com\r
+Transfer-Ignore: html\r
+Transfer-Preview: *\r
+\r
+0\r
+\r
+";
+        let mut headers = [EMPTY_HEADER; 16];
+        let mut res = Response::new(&mut headers[..]);
+        assert_eq!(res.parse(buf), Ok(Status::Complete(buf.len())));
+        let options = res.options().unwrap();
+        assert_eq!(options.methods.iter().collect::<Vec<_>>(), vec!["RESPMOD", "REQMOD"]);
+        assert!(options.supports(IcapMethod::Reqmod));
+        assert!(!options.supports(IcapMethod::Options));
+        assert_eq!(options.service, Some("FOO Tech Server 1.0"));
+        assert_eq!(options.istag, Some(ISTag::parse(b"\"W3E4R7U9-L2E4-2\"").unwrap()));
+        assert_eq!(options.encapsulated.unwrap().body().name(), SectionType::OptionsBody);
+        assert_eq!(options.max_connections, Some(1000));
+        assert_eq!(options.options_ttl, Some(::core::time::Duration::from_secs(7200)));
+        assert_eq!(options.date, Some("Mon, 10 Jan 2000  09:55:21 GMT"));
+        assert_eq!(options.service_id, Some("foo"));
+        assert!(options.allows(204));
+        assert!(options.allows(206));
+        assert!(!options.allows(200));
+        assert_eq!(options.preview, Some(2048));
+        assert_eq!(options.transfer("EXE"), Some(Transfer::Complete));
+        assert_eq!(options.transfer("html"), Some(Transfer::Ignore));
+        assert_eq!(options.transfer("txt"), Some(Transfer::Preview));
+    }
+
+    #[test]
+    fn test_options_response_defaults() {
+        let options = OptionsResponse::from_headers(&[]).unwrap();
+        assert!(options.methods.is_empty());
+        assert_eq!(options.istag, None);
+        assert_eq!(options.encapsulated, None);
+        assert_eq!(options.options_ttl, None);
+        assert!(!options.allows(204));
+        assert_eq!(options.transfer("exe"), None);
+    }
+
+    #[test]
+    fn test_options_response_invalid() {
+        let headers = [Header { name: "Options-TTL", value: b"soon" }];
+        assert_eq!(OptionsResponse::from_headers(&headers), Err(Error::HeaderValue));
+        let headers = [Header { name: "Service", value: b"\xff" }];
+        assert_eq!(OptionsResponse::from_headers(&headers), Err(Error::HeaderValue));
+        let headers = [Header { name: "Encapsulated", value: b"opt-body" }];
+        assert_eq!(OptionsResponse::from_headers(&headers), Err(Error::Encapsulated));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_token_list() {
+        let list = TokenList::new(" gif ,, JPG,\tpng ");
+        assert_eq!(list.iter().collect::<Vec<_>>(), vec!["gif", "JPG", "png"]);
+        assert!(list.contains("jpg"));
+        assert!(!list.contains("bmp"));
+        assert!(TokenList::new(" , ").is_empty());
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_istag() {
+        let tag = ISTag::parse(b" \"W3E4R7U9-L2E4-2\" ").unwrap();
+        assert_eq!(tag.as_str(), "W3E4R7U9-L2E4-2");
+        assert_eq!(tag.to_string(), "\"W3E4R7U9-L2E4-2\"");
+        assert_eq!(tag, ISTag::parse(b"\"W3E4R7U9-L2E4-2\"").unwrap());
+        assert_ne!(tag, ISTag::parse(b"\"W3E4R7U9-L2E4-3\"").unwrap());
+
+        assert_eq!(ISTag::parse(b"\"\"").unwrap().as_str(), "");
+        assert_eq!(ISTag::parse(b"\"a\\\"b\"").unwrap().as_str(), "a\\\"b");
+        assert!(ISTag::parse(b"\"0123456789abcdef0123456789abcdef\"").is_ok());
+    }
+
+    #[test]
+    fn test_istag_invalid() {
+        assert_eq!(ISTag::parse(b""), Err(Error::HeaderValue));
+        assert_eq!(ISTag::parse(b"\""), Err(Error::HeaderValue));
+        assert_eq!(ISTag::parse(b"W3E4R7U9"), Err(Error::HeaderValue));
+        assert_eq!(ISTag::parse(b"\"W3E4R7U9"), Err(Error::HeaderValue));
+        assert_eq!(ISTag::parse(b"\"a\"b\""), Err(Error::HeaderValue));
+        assert_eq!(ISTag::parse(b"\"a\\\""), Err(Error::HeaderValue));
+        assert_eq!(ISTag::parse(b"\"a\x01\""), Err(Error::HeaderValue));
+        assert_eq!(ISTag::parse(b"\"0123456789abcdef0123456789abcdefg\""), Err(Error::HeaderValue));
+    }
+
+    #[test]
+    fn test_response_istag() {
+        let mut headers = [EMPTY_HEADER; NUM_OF_HEADERS];
+        let mut res = Response::new(&mut headers[..]);
+        res.parse(b"ICAP/1.0 204 No Content\r\nISTag: W3E4\r\nEncapsulated: null-body=0\r\n\r\n").unwrap();
+        assert_eq!(res.istag(), Err(Error::HeaderValue));
+        assert_eq!(res.options(), Err(Error::HeaderValue));
+
+        let mut headers = [EMPTY_HEADER; NUM_OF_HEADERS];
+        let mut res = Response::new(&mut headers[..]);
+        res.parse(b"ICAP/1.0 204 No Content\r\nEncapsulated: null-body=0\r\n\r\n").unwrap();
+        assert_eq!(res.istag(), Ok(None));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_icap_method() {
+        assert_eq!(IcapMethod::from("REQMOD"), IcapMethod::Reqmod);
+        assert_eq!(IcapMethod::from("RESPMOD"), IcapMethod::Respmod);
+        assert_eq!(IcapMethod::from("OPTIONS"), IcapMethod::Options);
+        assert_eq!(IcapMethod::from("options"), IcapMethod::Extension("options"));
+        assert_eq!(IcapMethod::Extension("LOG").as_str(), "LOG");
+        assert_eq!(IcapMethod::Respmod.to_string(), "RESPMOD");
+
+        assert!(IcapMethod::Reqmod.requires_encapsulated());
+        assert!(IcapMethod::Extension("LOG").requires_encapsulated());
+        assert!(!IcapMethod::Options.requires_encapsulated());
+
+        assert!(IcapMethod::Reqmod.allows_section(SectionType::RequestBody));
+        assert!(!IcapMethod::Reqmod.allows_section(SectionType::ResponseHeader));
+        assert!(IcapMethod::Respmod.allows_section(SectionType::ResponseHeader));
+        assert!(!IcapMethod::Respmod.allows_section(SectionType::OptionsBody));
+        assert!(IcapMethod::Options.allows_section(SectionType::OptionsBody));
+        assert!(!IcapMethod::Options.allows_section(SectionType::RequestHeader));
+        assert!(IcapMethod::Extension("LOG").allows_section(SectionType::OptionsBody));
+    }
+
+    #[test]
+    fn test_request_icap_method() {
+        let mut headers = [EMPTY_HEADER; NUM_OF_HEADERS];
+        let mut req = Request::new(&mut headers[..]);
+        assert_eq!(req.icap_method(), None);
+        req.parse(b"LOG / ICAP/1.0\r\nEncapsulated: null-body=0\r\n\r\n").unwrap();
+        assert_eq!(req.icap_method(), Some(IcapMethod::Extension("LOG")));
+
+        let mut headers = [EMPTY_HEADER; NUM_OF_HEADERS];
+        let mut req = Request::new(&mut headers[..]);
+        assert_eq!(req.parse(b"LOG / ICAP/1.0\r\n\r\n"), Err(Error::MissingEncapsulated));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_icap_uri() {
+        let uri = IcapUri::parse("icaps://user@[::1]:11344/av/scan?mode=strict&verbose&&x=").unwrap();
+        assert_eq!(uri.scheme(), Some(Scheme::Icaps));
+        assert_eq!(uri.host(), Some("[::1]"));
+        assert_eq!(uri.explicit_port(), Some(11344));
+        assert_eq!(uri.port(), 11344);
+        assert_eq!(uri.path(), "/av/scan");
+        assert_eq!(uri.service(), "av/scan");
+        assert_eq!(uri.query(), Some("mode=strict&verbose&&x="));
+        assert_eq!(uri.query_params().collect::<Vec<_>>(),
+                   vec![("mode", "strict"), ("verbose", ""), ("x", "")]);
+
+        let uri = IcapUri::parse("ICAP://icap.example.org").unwrap();
+        assert_eq!(uri.scheme(), Some(Scheme::Icap));
+        assert_eq!(uri.host(), Some("icap.example.org"));
+        assert_eq!(uri.explicit_port(), None);
+        assert_eq!(uri.port(), 1344);
+        assert_eq!(uri.service(), "");
+        assert_eq!(uri.query_params().next(), None);
+
+        let uri = IcapUri::parse("/satisf?arg=87#top").unwrap();
+        assert_eq!(uri.scheme(), None);
+        assert_eq!(uri.host(), None);
+        assert_eq!(uri.service(), "satisf");
+        assert_eq!(uri.query(), Some("arg=87"));
+    }
+
+    #[test]
+    fn test_icap_uri_invalid() {
+        assert_eq!(IcapUri::parse("http://example.org/"), Err(Error::Uri));
+        assert_eq!(IcapUri::parse("icap:///service"), Err(Error::Uri));
+        assert_eq!(IcapUri::parse("icap://example.org:port/"), Err(Error::Uri));
+        assert_eq!(IcapUri::parse("icap://example.org:99999/"), Err(Error::Uri));
+        assert_eq!(IcapUri::parse("icap://[::1/"), Err(Error::Uri));
+        assert_eq!(IcapUri::parse("service"), Err(Error::Uri));
+        assert_eq!(IcapUri::parse("*"), Err(Error::Uri));
+    }
+
+    #[test]
+    fn test_request_uri() {
+        let mut headers = [EMPTY_HEADER; NUM_OF_HEADERS];
+        let mut req = Request::new(&mut headers[..]);
+        assert_eq!(req.uri(), Ok(None));
+        req.parse(b"REQMOD icap://icap-server.net/server?arg=87 ICAP/1.0\r\nEncapsulated: null-body=0\r\n\r\n").unwrap();
+        let uri = req.uri().unwrap().unwrap();
+        assert_eq!(uri.host(), Some("icap-server.net"));
+        assert_eq!(uri.service(), "server");
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_icap_status() {
+        for &code in &[100, 200, 204, 206, 400, 404, 405, 408, 418, 500, 501, 502, 503, 505] {
+            let status = IcapStatus::from(code);
+            assert_eq!(status.as_u16(), code);
+            assert!(status.canonical_reason().is_some());
+            assert_ne!(status, IcapStatus::Other(code));
+        }
+        assert_eq!(IcapStatus::from(299), IcapStatus::Other(299));
+        assert_eq!(u16::from(IcapStatus::Other(299)), 299);
+        assert_eq!(IcapStatus::Other(299).canonical_reason(), None);
+
+        assert!(IcapStatus::Continue.is_informational());
+        assert!(IcapStatus::PartialContent.is_success());
+        assert!(!IcapStatus::PartialContent.is_informational());
+        assert!(IcapStatus::BadComposition.is_client_error());
+        assert!(IcapStatus::VersionNotSupported.is_server_error());
+        assert!(!IcapStatus::Other(600).is_server_error());
+
+        assert_eq!(IcapStatus::ServiceNotFound.to_string(), "404 ICAP Service Not Found");
+        assert_eq!(IcapStatus::Other(299).to_string(), "299");
+    }
+
+    #[test]
+    fn test_response_status() {
+        let mut headers = [EMPTY_HEADER; NUM_OF_HEADERS];
+        let mut res = Response::new(&mut headers[..]);
+        assert_eq!(res.status(), None);
+        res.parse(b"ICAP/1.0 418 Bad Composition\r\nEncapsulated: null-body=0\r\n\r\n").unwrap();
+        assert_eq!(res.status(), Some(IcapStatus::BadComposition));
+
+        let res = ResponseBuilder::from_status(IcapStatus::NoContent);
+        let mut buf = [0; 64];
+        let len = res.encode(&mut buf).unwrap();
+        assert_eq!(&buf[..len], &b"ICAP/1.0 204 No Content\r\nEncapsulated: null-body=0\r\n\r\n"[..]);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_request_allow() {
+        let mut headers = [EMPTY_HEADER; NUM_OF_HEADERS];
+        let mut req = Request::new(&mut headers[..]);
+        req.parse(b"RESPMOD / ICAP/1.0\r\nAllow: 204, 206\r\nEncapsulated: res-body=0\r\n\r\n0\r\n\r\n").unwrap();
+        assert_eq!(req.allow().unwrap().iter().collect::<Vec<_>>(), vec!["204", "206"]);
+        assert_eq!(req.may_reply(IcapStatus::NoContent, false), Ok(true));
+        assert_eq!(req.may_reply(IcapStatus::PartialContent, false), Ok(true));
+
+        let mut headers = [EMPTY_HEADER; NUM_OF_HEADERS];
+        let mut req = Request::new(&mut headers[..]);
+        req.parse(b"RESPMOD / ICAP/1.0\r\nPreview: 4\r\nEncapsulated: res-body=0\r\n\r\n0\r\n\r\n").unwrap();
+        assert!(req.allow().unwrap().is_empty());
+        assert_eq!(req.may_reply(IcapStatus::NoContent, true), Ok(true));
+        assert_eq!(req.may_reply(IcapStatus::NoContent, false), Ok(false));
+        assert_eq!(req.may_reply(IcapStatus::PartialContent, true), Ok(false));
+        assert_eq!(req.may_reply(IcapStatus::Ok, false), Ok(true));
+
+        let mut headers = [EMPTY_HEADER; NUM_OF_HEADERS];
+        let mut req = Request::new(&mut headers[..]);
+        req.parse(b"RESPMOD / ICAP/1.0\r\nEncapsulated: res-body=0\r\n\r\n0\r\n\r\n").unwrap();
+        assert_eq!(req.may_reply(IcapStatus::NoContent, true), Ok(false));
+
+        let mut headers = [EMPTY_HEADER; NUM_OF_HEADERS];
+        let mut req = Request::new(&mut headers[..]);
+        req.parse(b"RESPMOD / ICAP/1.0\r\nPreview: x\r\nEncapsulated: res-body=0\r\n\r\n0\r\n\r\n").unwrap();
+        assert_eq!(req.may_reply(IcapStatus::NoContent, true), Err(Error::HeaderValue));
+    }
+
+    fn parse_detailed(buf: &[u8], num_headers: usize) -> ParseError<'_> {
+        let mut headers = [EMPTY_HEADER; NUM_OF_HEADERS];
+        let mut req = Request::new(&mut headers[..num_headers]);
+        req.parse_detailed(buf).unwrap_err()
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_parse_error_context() {
+        let err = parse_detailed(b"\r\nREQMOD / ICAP/1.0\r\nHost: a\r\nX-F@o: b\r\n\r\n", NUM_OF_HEADERS);
+        assert_eq!(err.kind(), Error::HeaderName);
+        assert_eq!(err.offset(), 33);
+        assert_eq!(err.byte(), Some(b'@'));
+        assert_eq!(err.header_index(), Some(1));
+        assert_eq!(err.header_name(), Some(&b"X-F"[..]));
+        assert_eq!(err.to_string(), "invalid header name at byte 33 (0x40) in header 1 \"X-F\"");
+        assert_eq!(Error::from(err), Error::HeaderName);
+
+        let err = parse_detailed(b"REQMOD / ICAP/1.0\r\nHost: a\r\nX-Foo: b\r\n\r\n", 1);
+        assert_eq!(err.kind(), Error::TooManyHeaders);
+        assert_eq!(err.offset(), 28);
+        assert_eq!(err.header_index(), Some(1));
+        assert_eq!(err.header_name(), Some(&b"X-Foo"[..]));
+
+        let err = parse_detailed(b"REQ\x01MOD / ICAP/1.0\r\n\r\n", NUM_OF_HEADERS);
+        assert_eq!(err.kind(), Error::Token);
+        assert_eq!(err.offset(), 3);
+        assert_eq!(err.byte(), Some(1));
+        assert_eq!(err.header_index(), None);
+    }
+
+    #[test]
+    fn test_parse_error_lenient_folded_header() {
+        let mut headers = [EMPTY_HEADER; NUM_OF_HEADERS];
+        let mut config = ParserConfig::default();
+        config.mode(ParseMode::Lenient);
+
+        let buf = b"REQMOD / ICAP/1.0\r\nX-Long: a,\r\n b\r\nX-Bad : c\x01\r\n\r\n";
+        let mut req = Request::new(&mut headers);
+        let err = config.parse_request_detailed(&mut req, buf).unwrap_err();
+        assert_eq!(err.kind(), Error::HeaderValue);
+        assert_eq!(err.byte(), Some(0x01));
+        assert_eq!(err.header_index(), Some(1));
+        assert_eq!(err.header_name(), Some(&b"X-Bad"[..]));
+
+        let buf = b"REQMOD / ICAP/1.0\r\nHost: a\r\nX-Long: a,\r\n\tb\x01\r\n\r\n";
+        let mut req = Request::new(&mut headers);
+        let err = config.parse_request_detailed(&mut req, buf).unwrap_err();
+        assert_eq!(err.kind(), Error::HeaderValue);
+        assert_eq!(err.header_index(), Some(1));
+        assert_eq!(err.header_name(), Some(&b"X-Long"[..]));
+    }
+
+    #[test]
+    fn test_parse_error_after_head() {
+        let buf = b"REQMOD / ICAP/1.0\r\nHost: a\r\nEncapsulated: res-hdr=0\r\n\r\n";
+        let err = parse_detailed(buf, NUM_OF_HEADERS);
+        assert_eq!(err.kind(), Error::Encapsulated);
+        assert_eq!(err.offset(), buf.len() - 1);
+        assert_eq!(err.header_index(), Some(1));
+        assert_eq!(err.header_name(), Some(&b"Encapsulated"[..]));
+
+        let buf = b"REQMOD / ICAP/1.0\r\n\r\n";
+        let err = parse_detailed(buf, NUM_OF_HEADERS);
+        assert_eq!(err.kind(), Error::MissingEncapsulated);
+        assert_eq!(err.offset(), buf.len() - 1);
+        assert_eq!(err.header_index(), None);
+
+        let buf = b"REQMOD / ICAP/1.0\r\nEncapsulated: req-body=0\r\n\r\n4\r\nRust\r\nx\r\n";
+        let err = parse_detailed(buf, NUM_OF_HEADERS);
+        assert_eq!(err.kind(), Error::Chunk);
+        assert_eq!(err.offset(), 56);
+        assert_eq!(err.byte(), Some(b'x'));
+        assert_eq!(err.header_index(), None);
+    }
+
+    #[test]
+    fn test_parse_detailed_ok() {
+        let mut headers = [EMPTY_HEADER; NUM_OF_HEADERS];
+        let mut res = Response::new(&mut headers[..]);
+        assert_eq!(res.parse_detailed(b"ICAP/1.0 200"), Ok(Status::Partial));
+        let err = res.parse_detailed(b"ICAP/1.0 2x0 OK\r\n\r\n").unwrap_err();
+        assert_eq!((err.kind(), err.offset()), (Error::Status, 10));
+        let buf = b"ICAP/1.0 100 Continue\r\n\r\n";
+        assert_eq!(res.parse_detailed(buf), Ok(Status::Complete(buf.len())));
+    }
+
+    #[cfg(feature = "codec")]
+    #[test]
+    fn test_server_codec() {
+        use bytes::BytesMut;
+        use tokio_util::codec::Decoder;
+        use super::{Frame, ServerCodec};
+
+        let mut codec = ServerCodec::new();
+        let mut buf = BytesMut::from(&b"RESPMOD icap://icap.example.org/satisf ICAP/1.0\r
+Preview: 4\r
+Encapsulated: res-hdr=0, res-body=19\r
+\r
+HTTP/1.1 200 OK\r
+\r
+4\r
+Ru"[..]);
+        let head = match codec.decode(&mut buf).unwrap() {
+            Some(Frame::Head(head)) => head,
+            frame => panic!("unexpected frame {:?}", frame),
+        };
+        assert_eq!(head.method, "RESPMOD");
+        assert_eq!(head.path, "icap://icap.example.org/satisf");
+        assert_eq!(head.headers.len(), 2);
+        assert_eq!(head.headers[0].name, "Preview");
+        assert_eq!(&head.headers[0].value[..], b"4");
+        assert_eq!(codec.decode(&mut buf).unwrap(),
+                   Some(Frame::Section(SectionType::ResponseHeader, b"HTTP/1.1 200 OK\r\n\r\n"[..].into())));
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(Frame::Body(b"Ru"[..].into())));
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+
+        buf.extend_from_slice(b"st\r\n0\r\n\r\n");
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(Frame::Body(b"st"[..].into())));
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(Frame::PreviewEnd));
+        assert!(codec.connection().is_preview_end());
+
+        codec.resume_preview();
+        buf.extend_from_slice(b"3\r\nace\r\n0\r\n\r\n");
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(Frame::Body(b"ace"[..].into())));
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(Frame::End));
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        assert!(buf.is_empty());
+    }
+
+    #[cfg(feature = "codec")]
+    #[test]
+    fn test_codec_limits() {
+        use bytes::BytesMut;
+        use tokio_util::codec::Decoder;
+        use super::{CodecError, Frame, ServerCodec};
+
+        let mut codec = ServerCodec::new();
+        codec.limits().max_head_len(64);
+        let mut buf = BytesMut::from(&b"REQMOD / ICAP/1.0\r\nEncapsulated: req-hdr=0, null-body=40\r\n\r\n"[..]);
+        assert!(codec.decode(&mut buf).unwrap().is_none());
+        buf.extend_from_slice(b"GET / HTTP/1.1\r\nHost: example.org\r\n\r\n");
+        match codec.decode(&mut buf) {
+            Err(CodecError::HeadTooLarge) => {},
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        let mut codec = ServerCodec::new();
+        codec.limits().max_head_len(16);
+        let mut buf = BytesMut::from(&b"REQMOD / ICAP/1.0\r\n"[..]);
+        match codec.decode(&mut buf) {
+            Err(CodecError::HeadTooLarge) => {},
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        let mut codec = ServerCodec::new();
+        codec.limits().max_body_len(Some(5));
+        let mut buf = BytesMut::from(&b"REQMOD / ICAP/1.0\r\nEncapsulated: req-body=0\r\n\r\n4\r\nRust\r\n3\r\nace\r\n0\r\n\r\n"[..]);
+        assert!(codec.decode(&mut buf).unwrap().is_some());
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(Frame::Body(b"Rust"[..].into())));
+        match codec.decode(&mut buf) {
+            Err(CodecError::BodyTooLarge) => {},
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        // a chunk size line that never ends
+        let mut config = ParserConfig::default();
+        config.max_chunk_line_len(Some(16));
+        let mut codec = ServerCodec::with_config(config);
+        let mut buf = BytesMut::from(&b"REQMOD / ICAP/1.0\r\nEncapsulated: req-body=0\r\n\r\n4;"[..]);
+        assert!(codec.decode(&mut buf).unwrap().is_some());
+        buf.extend_from_slice(b"ext=aaaaaaaaaa");
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        buf.extend_from_slice(b"a");
+        match codec.decode(&mut buf) {
+            Err(CodecError::Parse(Error::LineTooLong)) => {},
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        // the default limit on chunk lines
+        let mut codec = ServerCodec::new();
+        let mut buf = BytesMut::from(&b"REQMOD / ICAP/1.0\r\nEncapsulated: req-body=0\r\n\r\n4;"[..]);
+        assert!(codec.decode(&mut buf).unwrap().is_some());
+        buf.extend_from_slice(&[b'a'; 5000][..]);
+        match codec.decode(&mut buf) {
+            Err(CodecError::Parse(Error::LineTooLong)) => {},
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        // bytes buffered at the end of a preview are not a chunk line, but
+        // are bounded by the head limit
+        let mut codec = ServerCodec::new();
+        codec.limits().max_head_len(8 * 1024);
+        let mut buf = BytesMut::from(&b"REQMOD / ICAP/1.0\r\nPreview: 0\r\nEncapsulated: req-body=0\r\n\r\n0\r\n\r\n"[..]);
+        assert!(codec.decode(&mut buf).unwrap().is_some());
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(Frame::PreviewEnd));
+        buf.extend_from_slice(&[b'a'; 5000][..]);
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        buf.extend_from_slice(&[b'a'; 5000][..]);
+        match codec.decode(&mut buf) {
+            Err(CodecError::PreviewEndTooLarge) => {},
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        // bytes after the connection is closed are dropped
+        let mut codec = ServerCodec::new();
+        let mut buf = BytesMut::from(&b"OPTIONS / ICAP/1.0\r\nConnection: close\r\n\r\nOPTIONS / ICAP/1.0\r\n"[..]);
+        assert!(codec.decode(&mut buf).unwrap().is_some());
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(Frame::End));
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        assert!(buf.is_empty());
+
+        let mut codec = ServerCodec::new();
+        codec.limits().max_headers(1);
+        let mut buf = BytesMut::from(&b"REQMOD / ICAP/1.0\r\nHost: a\r\nEncapsulated: null-body=0\r\n\r\n"[..]);
+        match codec.decode(&mut buf) {
+            Err(CodecError::Parse(Error::TooManyHeaders)) => {},
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
+    #[cfg(feature = "codec")]
+    #[test]
+    fn test_codec_with_config() {
+        use bytes::BytesMut;
+        use tokio_util::codec::Decoder;
+        use super::{CodecError, Frame, ServerCodec};
+
+        let mut config = ParserConfig::default();
+        config.max_line_len(Some(16));
+        let mut codec = ServerCodec::with_config(config);
+        let mut buf = BytesMut::from(&b"REQMOD / ICAP/1.0\r\nEncapsulated: null-body=0\r\n\r\n"[..]);
+        match codec.decode(&mut buf) {
+            Err(CodecError::Parse(Error::LineTooLong)) => {},
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        let mut config = ParserConfig::default();
+        config.allow_any_sections(true);
+        let mut codec = ServerCodec::with_config(config);
+        let mut buf = BytesMut::from(&b"REQMOD / ICAP/1.0\r\nEncapsulated: res-hdr=0, null-body=19\r\n\r\nHTTP/1.1 200 OK\r\n\r\n"[..]);
+        match codec.decode(&mut buf).unwrap() {
+            Some(Frame::Head(head)) => assert_eq!(head.method, "REQMOD"),
+            frame => panic!("unexpected frame {:?}", frame),
+        }
+        assert_eq!(codec.decode(&mut buf).unwrap(),
+                   Some(Frame::Section(SectionType::ResponseHeader, b"HTTP/1.1 200 OK\r\n\r\n"[..].into())));
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(Frame::End));
+    }
+
+    #[cfg(feature = "codec")]
+    #[test]
+    fn test_client_codec() {
+        use bytes::BytesMut;
+        use tokio_util::codec::{Decoder, Encoder};
+        use super::{ClientCodec, Frame};
+
+        let mut codec = ClientCodec::new();
+        let mut buf = BytesMut::new();
+        codec.encode(RequestBuilder::new("OPTIONS", "icap://icap.example.org/filter"), &mut buf).unwrap();
+        assert_eq!(&buf[..], &b"OPTIONS icap://icap.example.org/filter ICAP/1.0\r\nEncapsulated: null-body=0\r\n\r\n"[..]);
+
+        let mut buf = BytesMut::from(&b"ICAP/1.0 204 No Content\r\nEncapsulated: null-body=0\r\n\r\n"[..]);
+        match codec.decode(&mut buf).unwrap() {
+            Some(Frame::Head(head)) => {
+                assert_eq!(head.code, 204);
+                assert_eq!(head.reason, "No Content");
+            },
+            frame => panic!("unexpected frame {:?}", frame),
+        }
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(Frame::End));
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_http_parts() {
+        use super::{encode_request_parts, encode_response_parts, request_parts, response_parts, HttpError};
+
+        let buf = b"RESPMOD icap://icap.example.org/satisf ICAP/1.0\r
+Encapsulated: req-hdr=0, res-hdr=44, res-body=108\r
+\r
+GET /origin-resource HTTP/1.0\r
+Host: www\r
+\r
+HTTP/1.1 200 OK\r
+Content-Type: text/plain\r
+Content-Length: 4\r
+\r
+4\r
+Rust\r
+0\r
+\r
+";
+        let mut headers = [EMPTY_HEADER; NUM_OF_HEADERS];
+        let mut req = Request::new(&mut headers[..]);
+        assert_eq!(req.parse(buf), Ok(Status::Complete(buf.len())));
+        let sections = req.sections.unwrap();
+
+        let req_parts = sections.request_parts().unwrap().unwrap();
+        assert_eq!(req_parts.method, ::http::Method::GET);
+        assert_eq!(req_parts.uri, "/origin-resource");
+        assert_eq!(req_parts.version, ::http::Version::HTTP_10);
+        assert_eq!(req_parts.headers["host"], "www");
+
+        let res_parts = sections.response_parts().unwrap().unwrap();
+        assert_eq!(res_parts.status, ::http::StatusCode::OK);
+        assert_eq!(res_parts.version, ::http::Version::HTTP_11);
+        assert_eq!(res_parts.headers.len(), 2);
+
+        assert_eq!(encode_request_parts(&req_parts), &b"GET /origin-resource HTTP/1.0\r\nhost: www\r\n\r\n"[..]);
+        let res_hdr = encode_response_parts(&res_parts);
+        assert_eq!(response_parts(&res_hdr).unwrap().headers, res_parts.headers);
+        assert!(res_hdr.starts_with(b"HTTP/1.1 200 OK\r\n"));
+
+        match request_parts(b"GET / HTTP/1.1\r\nHost: www\r\n") {
+            Err(HttpError::Incomplete) => {},
+            res => panic!("unexpected result {:?}", res),
+        }
+        match request_parts(b"GET / HTTP/1.1\r\nHo st: www\r\n\r\n") {
+            Err(err @ HttpError::Parse(httparse::Error::HeaderName)) => {
+                assert!(::std::error::Error::source(&err).is_some());
+            },
+            res => panic!("unexpected result {:?}", res),
+        }
+        match response_parts(b"HTTP/1.1 099 Weird\r\n\r\n") {
+            Err(HttpError::Http(_)) => {},
+            res => panic!("unexpected result {:?}", res),
+        }
+        let mut many = b"GET / HTTP/1.1\r\n".to_vec();
+        for _ in 0..101 {
+            many.extend_from_slice(b"X-Many: 1\r\n");
+        }
+        many.extend_from_slice(b"\r\n");
+        match request_parts(&many) {
+            Err(HttpError::TooManyHeaders) => {},
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
+    #[test]
+    fn test_parse_with_http() {
+        let buf = b"RESPMOD icap://icap.example.org/satisf ICAP/1.0\r
+Encapsulated: req-hdr=0, res-hdr=44, res-body=82\r
+\r
+GET /origin-resource HTTP/1.0\r
+Host: www\r
+\r
+HTTP/1.1 200 OK\r
+Content-Length: 4\r
+\r
+4\r
+Rust\r
+0\r
+\r
+";
+        let mut headers = [EMPTY_HEADER; NUM_OF_HEADERS];
+        let mut req_headers = [httparse::EMPTY_HEADER; 4];
+        let mut res_headers = [httparse::EMPTY_HEADER; 4];
+        let mut req = Request::new(&mut headers[..]);
+        let mut http_req = httparse::Request::new(&mut req_headers);
+        let mut http_res = httparse::Response::new(&mut res_headers);
+        assert_eq!(req.parse_with_http(buf, Some(&mut http_req), Some(&mut http_res)),
+                   Ok(Status::Complete(buf.len())));
+        assert_eq!(http_req.path, Some("/origin-resource"));
+        assert_eq!(http_req.version, Some(0));
+        assert_eq!(http_req.headers.len(), 1);
+        assert_eq!(http_res.code, Some(200));
+        assert_eq!(http_res.headers[0].name, "Content-Length");
+
+        let mut headers = [EMPTY_HEADER; NUM_OF_HEADERS];
+        let mut req = Request::new(&mut headers[..]);
+        let mut http_req = httparse::Request::new(&mut []);
+        assert_eq!(req.parse_with_http(buf, Some(&mut http_req), None), Err(Error::TooManyHeaders));
+
+        let mut headers = [EMPTY_HEADER; NUM_OF_HEADERS];
+        let mut res = Response::new(&mut headers[..]);
+        let mut http_res = httparse::Response::new(&mut res_headers);
+        let buf = b"ICAP/1.0 200 OK\r\nEncapsulated: res-hdr=0, null-body=15\r\n\r\nHTTP/1.1 OK\r\n\r\n";
+        assert_eq!(res.parse_with_http(buf, None, Some(&mut http_res)), Err(Error::HttpHead));
+        assert_eq!(res.parse_with_http(&buf[..20], None, Some(&mut http_res)), Ok(Status::Partial));
+    }
+
+    #[test]
+    fn test_parser_config_limits() {
+        let buf = b"RESPMOD icap://icap.example.org/filter ICAP/1.0\r\nHost: icap.example.org\r\nEncapsulated: res-hdr=0, res-body=19\r\n\r\nHTTP/1.1 200 OK\r\n\r\n5\r\nhello\r\n0\r\n\r\n";
+        let mut headers = [EMPTY_HEADER; NUM_OF_HEADERS];
+
+        let mut config = ParserConfig::default();
+        config.max_line_len(Some(47))
+            .max_headers_len(Some(64))
+            .max_encapsulated_offset(Some(19))
+            .max_chunk_size(Some(5));
+        let mut req = Request::new(&mut headers);
+        assert_eq!(config.parse_request(&mut req, buf), Ok(Status::Complete(buf.len())));
+
+        let mut req = Request::new(&mut headers);
+        config.max_line_len(Some(46));
+        assert_eq!(config.parse_request(&mut req, buf), Err(Error::LineTooLong));
+        // the limits are checked on incomplete heads too
+        let mut req = Request::new(&mut headers);
+        assert_eq!(config.parse_request(&mut req, &buf[..48]), Err(Error::LineTooLong));
+        config.max_line_len(None);
+
+        let mut req = Request::new(&mut headers);
+        config.max_headers_len(Some(63));
+        assert_eq!(config.parse_request(&mut req, buf), Err(Error::HeadersTooLarge));
+        config.max_headers_len(None);
+
+        let mut req = Request::new(&mut headers);
+        config.max_encapsulated_offset(Some(18));
+        assert_eq!(config.parse_request(&mut req, buf), Err(Error::EncapsulatedOffsetTooLarge));
+        config.max_encapsulated_offset(None);
+
+        let mut req = Request::new(&mut headers);
+        config.max_chunk_size(Some(4));
+        assert_eq!(config.parse_request(&mut req, buf), Err(Error::ChunkTooLarge));
+        assert_eq!(config.parse_chunk_size(b"5\r\n"), Err(Error::ChunkTooLarge));
+        assert_eq!(config.parse_chunk_size(b"4\r\n"), Ok(Status::Complete((3, 4))));
+        assert_eq!(config.parse_chunk_size(b"4"), Ok(Status::Partial));
+        assert_eq!(config.parse_chunk_size(b"x\r\n"), Err(Error::Chunk));
+
+        // the encapsulated sections do not count against the head limits
+        let res = b"ICAP/1.0 200 OK\r\nEncapsulated: res-hdr=0, null-body=60\r\n\r\nHTTP/1.1 200 OK\r\nX-Long-Header: aaaaaaaaaaaaaaaaaaaaaaaa\r\n\r\n";
+        let mut config = ParserConfig::default();
+        config.max_line_len(Some(40)).max_headers_len(Some(42));
+        let mut response = Response::new(&mut headers);
+        assert_eq!(config.parse_response(&mut response, res), Ok(Status::Complete(res.len())));
+        let mut response = Response::new(&mut headers);
+        config.max_encapsulated_offset(Some(59));
+        assert_eq!(config.parse_response(&mut response, res), Err(Error::EncapsulatedOffsetTooLarge));
+    }
+
+    #[test]
+    fn test_chunk_line_limit() {
+        let mut config = ParserConfig::default();
+        config.max_chunk_line_len(Some(8));
+        let decoder = ChunkedDecoder::new().with_config(&config);
+        assert_eq!(decoder.clone().decode(b"4;abcdef\r\nRust"), Ok(Status::Complete((10, Chunk::Head { size: 4, extensions: ChunkExtensions::new(b"abcdef") }))));
+        assert_eq!(decoder.clone().decode(b"4;abcdef\r"), Ok(Status::Partial));
+        assert_eq!(decoder.clone().decode(b"4;abcdefg\r\n"), Err(Error::LineTooLong));
+        // a line is rejected before it ends
+        assert_eq!(decoder.clone().decode(b"4;abcdefg"), Err(Error::LineTooLong));
+        assert_eq!(decoder.clone().decode(b"0\r\nX-Trailer: 1\r\n\r\n"), Err(Error::LineTooLong));
+        assert_eq!(decoder.clone().decode(b"0\r\nX-T: 1\r\n\r\n"), Ok(Status::Complete((13, Chunk::Last { extensions: ChunkExtensions::new(b"") }))));
+        assert_eq!(config.parse_chunk_size(b"4;abcdefg"), Err(Error::LineTooLong));
+
+        let buf = b"REQMOD / ICAP/1.0\r\nEncapsulated: req-body=0\r\n\r\n4;abcdefg\r\nRust\r\n0\r\n\r\n";
+        let mut headers = [EMPTY_HEADER; NUM_OF_HEADERS];
+        let mut req = Request::new(&mut headers);
+        assert_eq!(config.parse_request(&mut req, buf), Err(Error::LineTooLong));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_connection_chunk_limits() {
+        let mut config = ParserConfig::default();
+        config.max_chunk_line_len(Some(8)).max_chunk_size(Some(4));
+        let head = b"REQMOD / ICAP/1.0\r\nEncapsulated: req-body=0\r\n\r\n";
+
+        let mut conn = Connection::server().with_config(config);
+        let events = poll_all(&mut conn, head);
+        assert_eq!(events, vec!["request REQMOD"]);
+        assert_eq!(poll_all(&mut conn, b"4;abcdefgh"), vec!["error LineTooLong"]);
+
+        let mut conn = Connection::server().with_config(config);
+        poll_all(&mut conn, head);
+        assert_eq!(poll_all(&mut conn, b"5\r\nRust!\r\n0\r\n\r\n"), vec!["error ChunkTooLarge"]);
+    }
+
+    #[test]
+    fn test_encapsulated_any_case() {
+        let buf = b"RESPMOD / ICAP/1.0\r\nENCAPSULATED: res-hdr=0, null-body=19\r\n\r\nHTTP/1.1 200 OK\r\n\r\n";
+        let mut headers = [EMPTY_HEADER; NUM_OF_HEADERS];
+        let mut req = Request::new(&mut headers);
+        assert_eq!(req.parse(buf), Ok(Status::Complete(buf.len())));
+        assert_eq!(req.sections.unwrap().get(SectionType::ResponseHeader), Some(&b"HTTP/1.1 200 OK\r\n\r\n"[..]));
+
+        let buf = b"ICAP/1.0 200 OK\r\nencapsulated: res-hdr=0, null-body=19\r\n\r\nHTTP/1.1 200 OK\r\n\r\n";
+        let mut res = Response::new(&mut headers);
+        assert_eq!(res.parse(buf), Ok(Status::Complete(buf.len())));
+        assert!(res.sections.is_some());
+    }
+
+    #[test]
+    fn test_parse_mode_strict() {
+        let mut config = ParserConfig::default();
+        config.mode(ParseMode::Strict);
+        let mut headers = [EMPTY_HEADER; NUM_OF_HEADERS];
+
+        let buf = b"OPTIONS / ICAP/1.0\r\nHost: foo.bar\r\n\r\n";
+        let mut req = Request::new(&mut headers);
+        assert_eq!(config.parse_request(&mut req, buf), Ok(Status::Complete(buf.len())));
+
+        for buf in &[&b"OPTIONS / ICAP/1.0\nHost: foo.bar\r\n\r\n"[..],
+                     &b"OPTIONS / ICAP/1.0\r\nHost: foo.bar\n\r\n"[..],
+                     &b"OPTIONS / ICAP/1.0\r\nHost: foo.bar\r\n\n"[..],
+                     &b"\nOPTIONS / ICAP/1.0\r\nHost: foo.bar\r\n\r\n"[..]] {
+            let mut req = Request::new(&mut headers);
+            assert_eq!(config.parse_request(&mut req, buf), Err(Error::NewLine));
+            let mut req = Request::new(&mut headers);
+            assert_eq!(req.parse(buf), Ok(Status::Complete(buf.len())));
+        }
+
+        let buf = b"ICAP/1.0 200 OK\r\n\r\n";
+        let mut res = Response::new(&mut headers);
+        assert_eq!(config.parse_response(&mut res, buf), Ok(Status::Complete(buf.len())));
+        let buf = b"ICAP/1.0 200\r\n\r\n";
+        let mut res = Response::new(&mut headers);
+        assert_eq!(config.parse_response(&mut res, buf), Err(Error::Status));
+        let mut res = Response::new(&mut headers);
+        assert_eq!(res.parse(buf), Ok(Status::Complete(buf.len())));
+    }
+
+    #[test]
+    fn test_parse_mode_lenient() {
+        let mut config = ParserConfig::default();
+        config.mode(ParseMode::Lenient);
+        let mut headers = [EMPTY_HEADER; NUM_OF_HEADERS];
+
+        let buf = b"RESPMOD / ICAP/1.0\nHost \t: foo.bar\nEncapsulated: res-hdr=0,\r\n\tnull-body=19\r\nX-Empty:\r\n  folded\r\n\r\nHTTP/1.1 200 OK\r\n\r\n";
+        let mut req = Request::new(&mut headers);
+        assert_eq!(config.parse_request(&mut req, buf), Ok(Status::Complete(buf.len())));
+        assert_eq!(req.headers.len(), 3);
+        assert_eq!(req.headers[0].name, "Host");
+        assert_eq!(req.headers[0].value, b"foo.bar");
+        assert_eq!(req.headers[1].value, b"res-hdr=0,\r\n\tnull-body=19");
+        assert_eq!(req.headers[2].name, "X-Empty");
+        assert_eq!(req.headers[2].value, b"folded");
+        assert_eq!(req.sections.unwrap().get(SectionType::ResponseHeader), Some(&b"HTTP/1.1 200 OK\r\n\r\n"[..]));
+
+        // a folded line may still follow
+        let mut req = Request::new(&mut headers);
+        assert_eq!(config.parse_request(&mut req, &buf[..35]), Ok(Status::Partial));
+
+        let mut req = Request::new(&mut headers);
+        assert_eq!(req.parse(buf), Err(Error::HeaderName));
+        let mut req = Request::new(&mut headers);
+        assert_eq!(config.parse_request(&mut req, b"OPTIONS / ICAP/1.0\r\nHost x: foo.bar\r\n\r\n"), Err(Error::HeaderName));
+    }
+
+    #[test]
+    fn test_parse_mode_lenient_accessors() {
+        let mut config = ParserConfig::default();
+        config.mode(ParseMode::Lenient);
+        let mut headers = [EMPTY_HEADER; NUM_OF_HEADERS];
+
+        let buf = b"RESPMOD / ICAP/1.0\r\nPreview:\r\n 1024\r\n \r\nAllow: 206,\r\n 204\r\nEncapsulated: null-body=0\r\n\r\n";
+        let mut req = Request::new(&mut headers);
+        assert_eq!(config.parse_request(&mut req, buf), Ok(Status::Complete(buf.len())));
+        assert_eq!(req.headers[0].value, b"1024\r\n ");
+        assert_eq!(req.preview(), Ok(Some(1024)));
+        let allow = req.allow().unwrap();
+        assert!(allow.contains("204"));
+        assert!(allow.contains("206"));
+        assert_eq!(req.may_reply(IcapStatus::NoContent, false), Ok(true));
+
+        let buf = b"ICAP/1.0 200 OK\r\nMethods: REQMOD,\r\n RESPMOD\r\nService:\r\n\tFilter 1.0\r\nOptions-TTL: 3600\r\n\t\r\nEncapsulated: null-body=0\r\n\r\n";
+        let mut res = Response::new(&mut headers);
+        assert_eq!(config.parse_response(&mut res, buf), Ok(Status::Complete(buf.len())));
+        let options = res.options().unwrap();
+        assert!(options.supports(IcapMethod::Reqmod));
+        assert!(options.supports(IcapMethod::Respmod));
+        assert_eq!(options.service, Some("Filter 1.0"));
+        assert_eq!(options.options_ttl, Some(::core::time::Duration::from_secs(3600)));
+    }
+
+    #[test]
+    fn test_parser_config_detailed_and_http() {
+        let mut headers = [EMPTY_HEADER; NUM_OF_HEADERS];
+        let mut config = ParserConfig::default();
+        config.mode(ParseMode::Strict);
+
+        let buf = b"OPTIONS / ICAP/1.0\r\nHost: foo.bar\nX-Other: 1\r\n\r\n";
+        let mut req = Request::new(&mut headers);
+        let err = config.parse_request_detailed(&mut req, buf).unwrap_err();
+        assert_eq!(err.kind(), Error::NewLine);
+        assert_eq!(err.offset(), 33);
+        assert_eq!(err.header_name(), Some(&b"Host"[..]));
+
+        let buf = b"ICAP/1.0 200\r\n\r\n";
+        let mut res = Response::new(&mut headers);
+        let err = config.parse_response_detailed(&mut res, buf).unwrap_err();
+        assert_eq!(err.kind(), Error::Status);
+        assert_eq!(err.offset(), 12);
+
+        // the encapsulated HTTP head is parsed with the configuration too
+        let mut config = ParserConfig::default();
+        config.mode(ParseMode::Lenient);
+        let buf = b"ICAP/1.0 200 OK\r\nEncapsulated : res-hdr=0,\r\n null-body=19\r\n\r\nHTTP/1.1 200 OK\r\n\r\n";
+        let mut http_headers = [httparse::EMPTY_HEADER; 4];
+        let mut http_res = httparse::Response::new(&mut http_headers);
+        let mut res = Response::new(&mut headers);
+        assert_eq!(config.parse_response_with_http(&mut res, buf, None, Some(&mut http_res)),
+                   Ok(Status::Complete(buf.len())));
+        assert_eq!(http_res.code, Some(200));
+        let mut res = Response::new(&mut headers);
+        assert_eq!(res.parse_with_http(buf, None, Some(&mut http_res)), Err(Error::HeaderName));
+
+        config.max_encapsulated_offset(Some(18));
+        let mut headers = [EMPTY_HEADER; NUM_OF_HEADERS];
+        let mut req = Request::new(&mut headers);
+        let buf = b"RESPMOD / ICAP/1.0\r\nEncapsulated: res-hdr=0, null-body=19\r\n\r\nHTTP/1.1 200 OK\r\n\r\n";
+        assert_eq!(config.parse_request_with_http(&mut req, buf, None, Some(&mut http_res)),
+                   Err(Error::EncapsulatedOffsetTooLarge));
+    }
+
+    #[test]
+    fn test_request_preview_end() {
+        let mut headers = [EMPTY_HEADER; NUM_OF_HEADERS];
+
+        // a truncated preview
+        let buf = b"RESPMOD / ICAP/1.0\r\nPreview: 4\r\nEncapsulated: res-body=0\r\n\r\n4\r\nRust\r\n0\r\n\r\n";
+        let mut req = Request::new(&mut headers);
+        assert_eq!(req.parse(buf), Ok(Status::Complete(buf.len())));
+        assert_eq!(req.preview_ieof(), Some(false));
+        let sections = req.sections.unwrap();
+        assert!(!sections.is_body_complete());
+        assert!(sections.is_preview_end());
+        assert_eq!(sections.get(SectionType::ResponseBody), Some(&b"4\r\nRust\r\n0\r\n\r\n"[..]));
+
+        // the rest of the body is not part of this message
+        let mut long = [0; 128];
+        long[..buf.len()].copy_from_slice(buf);
+        long[buf.len()..buf.len() + 5].copy_from_slice(b"2\r\nab");
+        let mut req = Request::new(&mut headers);
+        assert_eq!(req.parse(&long[..buf.len() + 5]), Ok(Status::Complete(buf.len())));
+
+        // the whole body in the preview
+        let buf = b"RESPMOD / ICAP/1.0\r\nPreview: 4\r\nEncapsulated: res-body=0\r\n\r\n4\r\nRust\r\n0; ieof\r\n\r\n";
+        let mut req = Request::new(&mut headers);
+        assert_eq!(req.parse(buf), Ok(Status::Complete(buf.len())));
+        assert_eq!(req.preview_ieof(), Some(true));
+        let sections = req.sections.unwrap();
+        assert!(sections.is_body_complete());
+        assert!(!sections.is_preview_end());
+
+        // the end of the preview is not there yet
+        let mut req = Request::new(&mut headers);
+        assert_eq!(req.parse(&buf[..buf.len() - 12]), Ok(Status::Complete(buf.len() - 12)));
+        assert_eq!(req.preview_ieof(), None);
+
+        // without a preview, a zero-sized chunk ends the body
+        let buf = b"RESPMOD / ICAP/1.0\r\nEncapsulated: res-body=0\r\n\r\n4\r\nRust\r\n0\r\n\r\n";
+        let mut req = Request::new(&mut headers);
+        assert_eq!(req.parse(buf), Ok(Status::Complete(buf.len())));
+        assert_eq!(req.preview_ieof(), None);
+        assert!(req.sections.unwrap().is_body_complete());
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_sections_to_map() {
+        let buf = b"RESPMOD / ICAP/1.0\r\nEncapsulated: res-hdr=0, res-body=19\r\n\r\nHTTP/1.1 200 OK\r\n\r\n4\r\nRust\r\n0\r\n\r\n";
+        let mut headers = [EMPTY_HEADER; NUM_OF_HEADERS];
+        let mut req = Request::new(&mut headers);
+        assert_eq!(req.parse(buf), Ok(Status::Complete(buf.len())));
+        let map = req.sections.unwrap().to_map();
+        assert_eq!(map.len(), 2);
+        assert_eq!(map[&SectionType::ResponseHeader], b"HTTP/1.1 200 OK\r\n\r\n".to_vec());
+        assert_eq!(map[&SectionType::ResponseBody], b"4\r\nRust\r\n0\r\n\r\n".to_vec());
+    }
+
     #[cfg(feature = "std")]
     #[test]
+    #[allow(deprecated)]
     fn test_std_error() {
         use super::Error;
         use std::error::Error as StdError;
@@ -0,0 +1,192 @@
+use core::{result, str};
+
+use Error;
+
+/// The default port of ICAP services.
+pub const DEFAULT_PORT: u16 = 1344;
+
+/// The scheme of an absolute ICAP URI.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Scheme {
+    /// `icap`
+    Icap,
+    /// `icaps`, ICAP over TLS.
+    Icaps,
+}
+
+impl Scheme {
+    /// The scheme as it appears in a URI.
+    #[inline]
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Scheme::Icap => "icap",
+            Scheme::Icaps => "icaps",
+        }
+    }
+}
+
+/// A request target, such as `icap://icap.example.org:1344/filter?mode=strict`,
+/// split into its parts.
+///
+/// Both the absolute form and the origin form (`/filter`) are accepted. Only
+/// the absolute form has a scheme and host. Nothing is percent-decoded.
+///
+/// # Example
+///
+/// ```
+/// use icaparse::{IcapUri, Scheme};
+///
+/// let uri = IcapUri::parse("icap://icap-server.net/server?arg=87").unwrap();
+/// assert_eq!(uri.scheme(), Some(Scheme::Icap));
+/// assert_eq!(uri.host(), Some("icap-server.net"));
+/// assert_eq!(uri.port(), 1344);
+/// assert_eq!(uri.service(), "server");
+/// assert_eq!(uri.query_params().collect::<Vec<_>>(), vec![("arg", "87")]);
+///
+/// let uri = IcapUri::parse("/server").unwrap();
+/// assert_eq!(uri.host(), None);
+/// assert_eq!(uri.service(), "server");
+/// ```
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct IcapUri<'a> {
+    scheme: Option<Scheme>,
+    host: Option<&'a str>,
+    port: Option<u16>,
+    path: &'a str,
+    query: Option<&'a str>,
+}
+
+impl<'a> IcapUri<'a> {
+    /// Parse a request target.
+    ///
+    /// Returns `Error::Uri` if the scheme is not `icap` or `icaps`, if the
+    /// host is missing, if the port is not a number, or if an origin-form
+    /// target does not start with `/`.
+    pub fn parse(uri: &'a str) -> result::Result<IcapUri<'a>, Error> {
+        let rest = match uri.find('#') {
+            Some(i) => &uri[..i],
+            None => uri,
+        };
+        let (rest, query) = match rest.find('?') {
+            Some(i) => (&rest[..i], Some(&rest[i + 1..])),
+            None => (rest, None),
+        };
+
+        if rest.starts_with('/') {
+            return Ok(IcapUri { scheme: None, host: None, port: None, path: rest, query });
+        }
+
+        let i = rest.find("://").ok_or(Error::Uri)?;
+        let scheme = match &rest[..i] {
+            s if s.eq_ignore_ascii_case("icap") => Scheme::Icap,
+            s if s.eq_ignore_ascii_case("icaps") => Scheme::Icaps,
+            _ => return Err(Error::Uri),
+        };
+        let rest = &rest[i + 3..];
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        // skip any userinfo
+        let authority = match authority.rfind('@') {
+            Some(i) => &authority[i + 1..],
+            None => authority,
+        };
+        let (host, port) = if authority.starts_with('[') {
+            // an IPv6 literal
+            let end = authority.find(']').ok_or(Error::Uri)?;
+            (&authority[..end + 1], &authority[end + 1..])
+        } else {
+            match authority.find(':') {
+                Some(i) => (&authority[..i], &authority[i..]),
+                None => (authority, ""),
+            }
+        };
+        let port = match port {
+            "" => None,
+            port if port.starts_with(':') => Some(parse_port(&port[1..])?),
+            _ => return Err(Error::Uri),
+        };
+        if host.is_empty() {
+            return Err(Error::Uri);
+        }
+        Ok(IcapUri { scheme: Some(scheme), host: Some(host), port, path, query })
+    }
+
+    /// The scheme, for an absolute URI.
+    #[inline]
+    pub fn scheme(&self) -> Option<Scheme> {
+        self.scheme
+    }
+
+    /// The host, for an absolute URI. IPv6 addresses keep their brackets.
+    #[inline]
+    pub fn host(&self) -> Option<&'a str> {
+        self.host
+    }
+
+    /// The port given in the URI, if any.
+    #[inline]
+    pub fn explicit_port(&self) -> Option<u16> {
+        self.port
+    }
+
+    /// The port given in the URI, or the default ICAP port, 1344.
+    #[inline]
+    pub fn port(&self) -> u16 {
+        self.port.unwrap_or(DEFAULT_PORT)
+    }
+
+    /// The path, such as `/filter`. It always starts with `/`.
+    #[inline]
+    pub fn path(&self) -> &'a str {
+        self.path
+    }
+
+    /// The service name: the path without its leading `/`.
+    #[inline]
+    pub fn service(&self) -> &'a str {
+        &self.path[1..]
+    }
+
+    /// The raw query string, without the `?`.
+    #[inline]
+    pub fn query(&self) -> Option<&'a str> {
+        self.query
+    }
+
+    /// Iterates over the `name=value` pairs of the query string.
+    ///
+    /// A parameter without `=` has an empty value.
+    #[inline]
+    pub fn query_params(&self) -> QueryParams<'a> {
+        QueryParams { inner: self.query.unwrap_or("").split('&') }
+    }
+}
+
+/// An iterator over the parameters of a query string.
+#[derive(Clone, Debug)]
+pub struct QueryParams<'a> {
+    inner: str::Split<'a, char>,
+}
+
+impl<'a> Iterator for QueryParams<'a> {
+    type Item = (&'a str, &'a str);
+
+    fn next(&mut self) -> Option<(&'a str, &'a str)> {
+        self.inner.by_ref()
+            .find(|param| !param.is_empty())
+            .map(|param| match param.find('=') {
+                Some(i) => (&param[..i], &param[i + 1..]),
+                None => (param, ""),
+            })
+    }
+}
+
+#[inline]
+fn parse_port(port: &str) -> result::Result<u16, Error> {
+    if port.is_empty() || !port.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(Error::Uri);
+    }
+    port.parse().map_err(|_| Error::Uri)
+}